@@ -0,0 +1,294 @@
+//! Prepares the contract artifacts that are embedded in the binary and
+//! generates typed bindings for them. Only the artifacts checked into the
+//! `abi` directory are embedded, so that the binary doesn't depend on what
+//! happens to be built locally. These are full artifacts with bytecode that
+//! are exported from the truffle build with `npm run export-artifacts` when
+//! the contracts change, artifacts without bytecode are embedded with a
+//! warning since the client can't deploy or verify those contracts.
+//!
+//! Deployed networks are always stripped from the embedded artifacts, since
+//! the addresses of a local development chain are meaningless anywhere else.
+
+#[path = "src/bindgen.rs"]
+mod bindgen;
+
+use bindgen::{canonical_type, param_type, signature};
+use serde_json::{json, Value};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const CONTRACTS: &[&str] = &["ScamIco", "WETH9", "MagicWeth", "Scam"];
+
+/// The contracts to generate bindings for. WETH9 is omitted since MagicWeth
+/// has the same interface with the addition of `magicallyCreate`.
+const BINDINGS: &[&str] = &["ScamIco", "MagicWeth", "Scam"];
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR not set by cargo");
+    let out_dir = Path::new(&out_dir);
+
+    let mut bindings = String::new();
+    for name in CONTRACTS {
+        let path = Path::new("abi").join(format!("{}.json", name));
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let json = fs::read_to_string(&path).expect("failed to read contract artifact");
+        let artifact = embedded_artifact(
+            name,
+            serde_json::from_str(&json).expect("failed to parse contract artifact"),
+        );
+
+        if artifact["bytecode"]
+            .as_str()
+            .unwrap_or("")
+            .trim_start_matches("0x")
+            .is_empty()
+        {
+            println!(
+                "cargo:warning={} has no bytecode, export the full artifacts with `npm run export-artifacts`",
+                path.display()
+            );
+        }
+
+        if BINDINGS.contains(name) {
+            bindings.push_str(&generate_bindings(name, &artifact["abi"]));
+        }
+
+        fs::write(out_dir.join(format!("{}.json", name)), artifact.to_string())
+            .expect("failed to write embedded artifact");
+    }
+
+    fs::write(out_dir.join("bindings.rs"), bindings).expect("failed to write bindings");
+}
+
+/// Turns a truffle artifact or a bare ABI into the artifact that gets embedded
+/// in the binary, without any deployed networks.
+fn embedded_artifact(name: &str, artifact: Value) -> Value {
+    let mut artifact = match artifact {
+        Value::Array(_) => json!({ "contractName": name, "abi": artifact }),
+        artifact => artifact,
+    };
+    artifact
+        .as_object_mut()
+        .expect("contract artifact is not an object")
+        .remove("networks");
+    artifact
+}
+
+/// Generates a struct for a contract with a method for each of its functions.
+/// Functions that don't modify state are called and resolve to their return
+/// values, all others return a transaction builder.
+fn generate_bindings(name: &str, abi: &Value) -> String {
+    let mut code = String::new();
+    writeln!(code, "/// Typed bindings for the `{}` contract.", name).unwrap();
+    writeln!(code, "#[derive(Clone)]").unwrap();
+    writeln!(code, "pub struct {}<T: web3::Transport> {{", name).unwrap();
+    writeln!(code, "    contract: crate::contract::Contract<T>,").unwrap();
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(code, "impl<T: web3::Transport> {}<T> {{", name).unwrap();
+    writeln!(
+        code,
+        "    pub fn new(contract: crate::contract::Contract<T>) -> {}<T> {{",
+        name
+    )
+    .unwrap();
+    writeln!(code, "        {} {{ contract }}", name).unwrap();
+    writeln!(code, "    }}\n").unwrap();
+    writeln!(code, "    pub fn address(&self) -> web3::types::Address {{").unwrap();
+    writeln!(code, "        self.contract.address()").unwrap();
+    writeln!(code, "    }}\n").unwrap();
+    writeln!(
+        code,
+        "    pub fn contract(&self) -> &crate::contract::Contract<T> {{"
+    )
+    .unwrap();
+    writeln!(code, "        &self.contract").unwrap();
+    writeln!(code, "    }}").unwrap();
+
+    let functions = abi
+        .as_array()
+        .expect("contract ABI is not an array")
+        .iter()
+        .filter(|entry| entry["type"] == "function");
+    let mut names = Vec::new();
+    for function in functions {
+        match generate_function(function, &mut names) {
+            Some(method) => code.push_str(&method),
+            None => println!(
+                "cargo:warning=skipping {}.{} with unsupported parameter types",
+                name, function["name"]
+            ),
+        }
+    }
+
+    writeln!(code, "}}\n").unwrap();
+    code
+}
+
+fn generate_function(function: &Value, names: &mut Vec<String>) -> Option<String> {
+    let name = function["name"].as_str().expect("function without a name");
+    let inputs = params(&function["inputs"])?;
+    let outputs = params(&function["outputs"])?;
+    let constant = function["constant"] == true
+        || function["stateMutability"] == "view"
+        || function["stateMutability"] == "pure";
+
+    // overloaded functions get a numeric suffix
+    let base = identifier(&snake_case(name));
+    let overloads = names.iter().filter(|other| **other == base).count();
+    names.push(base.clone());
+    let method = match overloads {
+        0 => base,
+        n => format!("{}{}", base, n),
+    };
+
+    let types: Vec<_> = inputs.iter().map(|param| param.solidity.as_str()).collect();
+    let signature = signature(name, &types);
+    let args = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, param)| format!(", {}: {}", arg_name(&param.name, i), param.rust))
+        .collect::<String>();
+    let tokens = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            format!(
+                "web3::contract::tokens::Tokenizable::into_token({}), ",
+                arg_name(&param.name, i)
+            )
+        })
+        .collect::<String>();
+
+    let mut code = String::new();
+    writeln!(code).unwrap();
+    writeln!(code, "    /// Calls the `{}` contract function.", signature).unwrap();
+    if constant && !outputs.is_empty() {
+        let output = if outputs.len() == 1 {
+            outputs[0].rust.clone()
+        } else {
+            format!(
+                "({})",
+                outputs
+                    .iter()
+                    .map(|param| param.rust.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        writeln!(
+            code,
+            "    pub fn {}(&self{}) -> impl web3::futures::Future<Item = {}, Error = web3::contract::Error> {{",
+            method, args, output
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            code,
+            "    pub fn {}(&self{}) -> crate::contract::ContractTransactionBuilder<T> {{",
+            method, args
+        )
+        .unwrap();
+    }
+
+    writeln!(code, "        let function = ethabi::Function {{").unwrap();
+    writeln!(code, "            name: {:?}.to_string(),", name).unwrap();
+    writeln!(code, "            inputs: vec![{}],", param_list(&inputs)).unwrap();
+    writeln!(code, "            outputs: vec![{}],", param_list(&outputs)).unwrap();
+    writeln!(code, "            constant: {},", constant).unwrap();
+    writeln!(code, "        }};").unwrap();
+    writeln!(
+        code,
+        "        let tx = self.contract.typed_function(function, vec![{}]);",
+        tokens
+    )
+    .unwrap();
+    if constant && !outputs.is_empty() {
+        writeln!(code, "        tx.call()").unwrap();
+    } else {
+        writeln!(code, "        tx").unwrap();
+    }
+    writeln!(code, "    }}").unwrap();
+
+    Some(code)
+}
+
+struct Param {
+    name: String,
+    solidity: String,
+    rust: String,
+    kind: String,
+}
+
+fn params(params: &Value) -> Option<Vec<Param>> {
+    params
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|param| {
+            let solidity = canonical_type(param["type"].as_str()?);
+            let (rust, kind) = param_type(&solidity)?;
+            Some(Param {
+                name: param["name"].as_str().unwrap_or_default().to_string(),
+                solidity,
+                rust: rust.to_string(),
+                kind,
+            })
+        })
+        .collect()
+}
+
+fn param_list(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|param| {
+            format!(
+                "ethabi::Param {{ name: {:?}.to_string(), kind: {} }}, ",
+                param.name, param.kind
+            )
+        })
+        .collect()
+}
+
+fn arg_name(name: &str, index: usize) -> String {
+    let name = snake_case(name.trim_matches('_'));
+    if name.is_empty() {
+        format!("p{}", index)
+    } else {
+        identifier(&name)
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// Escapes Rust keywords and names that clash with the generated methods.
+fn identifier(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "address", "as", "break", "const", "continue", "contract", "crate", "else", "enum", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "new", "pub",
+        "ref", "return", "self", "static", "struct", "super", "trait", "type", "use", "where",
+        "while",
+    ];
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
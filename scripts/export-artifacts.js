@@ -0,0 +1,25 @@
+// Exports the truffle artifacts of the contracts embedded in the client binary
+// to the `abi` directory. Deployed networks and machine specific source paths
+// are left out so that the exported artifacts can be checked in.
+
+const fs = require("fs");
+const path = require("path");
+
+const CONTRACTS = ["ScamIco", "WETH9", "MagicWeth", "Scam"];
+
+for (const name of CONTRACTS) {
+  const artifact = require(`../build/contracts/${name}.json`);
+  const exported = {
+    contractName: artifact.contractName,
+    abi: artifact.abi,
+    bytecode: artifact.bytecode,
+    deployedBytecode: artifact.deployedBytecode,
+    sourceMap: artifact.sourceMap,
+    deployedSourceMap: artifact.deployedSourceMap,
+    compiler: artifact.compiler,
+  };
+
+  const file = path.join(__dirname, "..", "abi", `${name}.json`);
+  fs.writeFileSync(file, JSON.stringify(exported, null, 2) + "\n");
+  console.log(`exported ${name} to ${path.relative(process.cwd(), file)}`);
+}
@@ -0,0 +1,132 @@
+use crate::amount::Amount;
+use crate::contract::{EventLog, EventSubscription};
+use crate::events::{Approval, Deposit, Transfer};
+use ethabi::Log as AbiLog;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use web3::contract::Error as Web3ContractError;
+use web3::futures::{stream, Stream};
+use web3::types::{Address, H256};
+use web3::Transport;
+
+/// Subscriptions to the contract events that make up the ICO activity feed.
+pub struct Activity<T: Transport> {
+    subscriptions: Vec<(ActivityKind, EventSubscription<T>)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ActivityKind {
+    /// WETH deposits.
+    Deposit,
+    /// WETH approvals for the ICO.
+    Approval,
+    /// WETH transfers to the ICO.
+    Contribution,
+    /// SCM transfers from the zero address.
+    Mint,
+}
+
+impl<T: Transport> Activity<T> {
+    pub fn new(subscriptions: Vec<(ActivityKind, EventSubscription<T>)>) -> Activity<T> {
+        Activity { subscriptions }
+    }
+
+    /// Turns the subscriptions into a single stream of activity that polls
+    /// each subscription for changes at the specified interval. The stream
+    /// ends with an error as soon as polling one of the subscriptions fails.
+    pub fn stream(
+        self,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = ActivityEvent, Error = Web3ContractError>
+    where
+        T: 'static,
+    {
+        let empty: ActivityStream = Box::new(stream::empty());
+        self.subscriptions
+            .into_iter()
+            .fold(empty, move |merged, (kind, subscription)| {
+                let events = subscription
+                    .stream(poll_interval)
+                    .and_then(move |log| ActivityEvent::decode(kind, log));
+                Box::new(merged.select(events))
+            })
+    }
+}
+
+type ActivityStream = Box<dyn Stream<Item = ActivityEvent, Error = Web3ContractError>>;
+
+#[derive(Clone, Debug)]
+pub struct ActivityEvent {
+    pub kind: ActivityKind,
+    pub account: Address,
+    pub amount: Amount,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<H256>,
+    log_index: Option<u64>,
+}
+
+impl ActivityEvent {
+    /// The position of the event in the chain, used to order events from
+    /// different subscriptions.
+    pub fn position(&self) -> (Option<u64>, Option<u64>) {
+        (self.block_number, self.log_index)
+    }
+
+    fn decode(
+        kind: ActivityKind,
+        log: EventLog<AbiLog>,
+    ) -> Result<ActivityEvent, Web3ContractError> {
+        let (account, value, log) = match kind {
+            ActivityKind::Deposit => {
+                let log = log.detokenize::<Deposit>()?;
+                (log.data.owner, log.data.value, log)
+            }
+            ActivityKind::Approval => {
+                let log = log.detokenize::<Approval>()?;
+                (log.data.owner, log.data.value, log)
+            }
+            ActivityKind::Contribution => {
+                let log = log.detokenize::<Transfer>()?;
+                (log.data.from, log.data.value, log)
+            }
+            ActivityKind::Mint => {
+                let log = log.detokenize::<Transfer>()?;
+                (log.data.to, log.data.value, log)
+            }
+        };
+
+        Ok(ActivityEvent {
+            kind,
+            account,
+            amount: Amount::new(value, 18),
+            block_number: log.block_number,
+            transaction_hash: log.transaction_hash,
+            log_index: log.log_index.map(|index| index.as_u64()),
+        })
+    }
+}
+
+impl Display for ActivityEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let block = match self.block_number {
+            Some(block) => format!("#{}", block),
+            None => "pending".to_string(),
+        };
+        let (action, token) = match self.kind {
+            ActivityKind::Deposit => ("deposited", "WETH"),
+            ActivityKind::Approval => ("approved", "WETH"),
+            ActivityKind::Contribution => ("contributed", "WETH"),
+            ActivityKind::Mint => ("was minted", "SCM"),
+        };
+
+        write!(
+            f,
+            "[{:>8}] {:?} {} {} {}",
+            block, self.account, action, self.amount, token
+        )?;
+        if let Some(hash) = self.transaction_hash {
+            write!(f, " (tx {:?})", hash)?;
+        }
+        Ok(())
+    }
+}
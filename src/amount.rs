@@ -0,0 +1,239 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+use web3::types::U256;
+
+/// An exact fixed-point token amount represented as a raw integer value along
+/// with the number of decimals, i.e. the amount `raw / 10^decimals`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Amount {
+    raw: U256,
+    decimals: u8,
+}
+
+impl Amount {
+    pub fn new(raw: U256, decimals: u8) -> Amount {
+        Amount { raw, decimals }
+    }
+
+    pub fn raw(&self) -> U256 {
+        self.raw
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    /// Converts a token's on-chain `decimals()` value to a number of decimals,
+    /// failing if it does not fit in a `u8`.
+    pub fn checked_decimals(decimals: U256) -> Result<u8, AmountError> {
+        if decimals > U256::from(u8::max_value()) {
+            return Err(AmountError::InvalidDecimals(decimals));
+        }
+        Ok(decimals.low_u32() as u8)
+    }
+
+    /// Converts the amount to a different number of decimals, failing if the
+    /// conversion would overflow or lose precision.
+    pub fn rescale(self, decimals: u8) -> Result<Amount, AmountError> {
+        if decimals >= self.decimals {
+            let factor = pow10(decimals - self.decimals)?;
+            let raw = self.raw.checked_mul(factor).ok_or(AmountError::Overflow)?;
+            Ok(Amount::new(raw, decimals))
+        } else {
+            let factor = pow10(self.decimals - decimals)?;
+            let (raw, remainder) = self.raw.div_mod(factor);
+            if !remainder.is_zero() {
+                return Err(AmountError::PrecisionLoss(self, decimals));
+            }
+            Ok(Amount::new(raw, decimals))
+        }
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    /// Parses a decimal string into an amount with as many decimals as there
+    /// are fractional digits in the string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AmountError::InvalidFormat(s.to_string());
+
+        let s = s.trim();
+        let (whole, fraction) = match s.find('.') {
+            Some(i) if i == 0 || i == s.len() - 1 => return Err(invalid()),
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, ""),
+        };
+        if whole.is_empty()
+            || !whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        if fraction.len() > u8::max_value() as usize {
+            return Err(AmountError::Overflow);
+        }
+
+        let digits = format!("{}{}", whole, fraction);
+        let raw = U256::from_dec_str(&digits).map_err(|_| AmountError::Overflow)?;
+
+        Ok(Amount::new(raw, fraction.len() as u8))
+    }
+}
+
+impl Display for Amount {
+    /// Formats the amount as an exact decimal. A precision truncates the
+    /// fractional digits, otherwise trailing zeros are omitted.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let decimals = self.decimals as usize;
+        let digits = format!("{:0>1$}", self.raw.to_string(), decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+
+        let fraction = match f.precision() {
+            Some(precision) if precision <= decimals => fraction[..precision].to_string(),
+            Some(precision) => format!("{:0<1$}", fraction, precision),
+            None => fraction.trim_end_matches('0').to_string(),
+        };
+        let formatted = if fraction.is_empty() {
+            whole.to_string()
+        } else {
+            format!("{}.{}", whole, fraction)
+        };
+
+        match f.width() {
+            Some(width) => write!(f, "{:>1$}", formatted, width),
+            None => f.write_str(&formatted),
+        }
+    }
+}
+
+fn pow10(exponent: u8) -> Result<U256, AmountError> {
+    U256::from(10)
+        .checked_pow(exponent.into())
+        .ok_or(AmountError::Overflow)
+}
+
+#[derive(Debug, Error)]
+pub enum AmountError {
+    #[error("invalid decimal amount '{0}'")]
+    InvalidFormat(String),
+
+    #[error("amount overflows a 256-bit integer")]
+    Overflow,
+
+    #[error("amount {0} can not be represented with {1} decimals")]
+    PrecisionLoss(Amount, u8),
+
+    #[error("token decimals {0} exceed the maximum of 255")]
+    InvalidDecimals(U256),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(raw: u64, decimals: u8) -> Amount {
+        Amount::new(raw.into(), decimals)
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("0".parse::<Amount>().unwrap(), amount(0, 0));
+        assert_eq!("42".parse::<Amount>().unwrap(), amount(42, 0));
+        assert_eq!(" 1.50 ".parse::<Amount>().unwrap(), amount(150, 2));
+        assert_eq!("0.000001".parse::<Amount>().unwrap(), amount(1, 6));
+        assert_eq!(
+            "1.000000000000000000".parse::<Amount>().unwrap(),
+            amount(1_000_000_000_000_000_000, 18)
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for s in &["", ".", ".5", "5.", "1.2.3", "-1", "1e18", "0x10", "1,5"] {
+            match s.parse::<Amount>() {
+                Err(AmountError::InvalidFormat(_)) => {}
+                result => panic!("unexpected result for '{}': {:?}", s, result),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_overflow() {
+        let too_many_digits = format!("0.{}", "0".repeat(256));
+        assert!(format!("0.{}", "0".repeat(255)).parse::<Amount>().is_ok());
+        match too_many_digits.parse::<Amount>() {
+            Err(AmountError::Overflow) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let max = U256::max_value().to_string();
+        assert_eq!(
+            max.parse::<Amount>().unwrap(),
+            Amount::new(U256::max_value(), 0)
+        );
+        match format!("{}0", max).parse::<Amount>() {
+            Err(AmountError::Overflow) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(amount(0, 0).to_string(), "0");
+        assert_eq!(amount(0, 18).to_string(), "0");
+        assert_eq!(amount(123, 0).to_string(), "123");
+        assert_eq!(amount(1, 18).to_string(), "0.000000000000000001");
+        assert_eq!(amount(1_500_000_000_000_000_000, 18).to_string(), "1.5");
+        assert_eq!(format!("{:.2}", amount(1_234_500, 6)), "1.23");
+        assert_eq!(format!("{:.3}", amount(15, 1)), "1.500");
+        assert_eq!(format!("{:8.1}", amount(15, 1)), "     1.5");
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in &["0", "1", "0.1", "123.456", "0.000000000000000001"] {
+            assert_eq!(s.parse::<Amount>().unwrap().to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn rescale() {
+        assert_eq!(
+            amount(15, 1).rescale(18).unwrap(),
+            amount(1_500_000_000_000_000_000, 18)
+        );
+        assert_eq!(
+            amount(1_500_000_000_000_000_000, 18).rescale(1).unwrap(),
+            amount(15, 1)
+        );
+        assert_eq!(amount(7, 0).rescale(0).unwrap(), amount(7, 0));
+        assert_eq!(amount(7_000, 3).rescale(0).unwrap(), amount(7, 0));
+
+        match amount(1, 18).rescale(0) {
+            Err(AmountError::PrecisionLoss(..)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match Amount::new(U256::max_value(), 0).rescale(18) {
+            Err(AmountError::Overflow) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match amount(1, 0).rescale(255) {
+            Err(AmountError::Overflow) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn checked_decimals() {
+        assert_eq!(Amount::checked_decimals(18.into()).unwrap(), 18);
+        assert_eq!(Amount::checked_decimals(255.into()).unwrap(), 255);
+        match Amount::checked_decimals(256.into()) {
+            Err(AmountError::InvalidDecimals(_)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
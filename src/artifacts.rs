@@ -0,0 +1,531 @@
+use crate::truffle::{Artifact, ArtifactError, Network};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use web3::types::{Address, H256};
+
+/// A source of compiled contract artifacts.
+pub trait ArtifactSource {
+    /// Loads the artifact for the contract with the specified name.
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError>;
+}
+
+impl<S> ArtifactSource for Box<S>
+where
+    S: ArtifactSource + ?Sized,
+{
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError> {
+        (**self).load(name)
+    }
+}
+
+/// Detects the kind of project in a directory from its configuration files
+/// and build output, defaulting to a truffle project.
+pub fn detect<P>(project: P) -> Box<dyn ArtifactSource>
+where
+    P: AsRef<Path>,
+{
+    let root = project.as_ref().to_path_buf();
+    let exists = |path: &str| root.join(path).exists();
+
+    if exists("hardhat.config.js") || exists("hardhat.config.ts") {
+        Box::new(HardhatProject(root))
+    } else if exists("foundry.toml") {
+        Box::new(FoundryProject(root))
+    } else if exists("truffle-config.js") || exists("truffle.js") || exists("build/contracts") {
+        Box::new(TruffleProject(root))
+    } else if exists("artifacts") {
+        Box::new(HardhatProject(root))
+    } else if exists("out") {
+        Box::new(FoundryProject(root))
+    } else {
+        Box::new(TruffleProject(root))
+    }
+}
+
+/// The ScamIco, WETH9, MagicWeth and Scam artifacts embedded in the binary at
+/// build time. These never include deployed networks, and include bytecode
+/// when the artifacts checked into the `abi` directory do, or the contracts
+/// were built with truffle before building the binary.
+pub struct EmbeddedArtifacts;
+
+impl ArtifactSource for EmbeddedArtifacts {
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError> {
+        let json = match name {
+            "ScamIco" => include_str!(concat!(env!("OUT_DIR"), "/ScamIco.json")),
+            "WETH9" => include_str!(concat!(env!("OUT_DIR"), "/WETH9.json")),
+            "MagicWeth" => include_str!(concat!(env!("OUT_DIR"), "/MagicWeth.json")),
+            "Scam" => include_str!(concat!(env!("OUT_DIR"), "/Scam.json")),
+            _ => return Err(ArtifactError::NotFound(name.to_string())),
+        };
+
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// Loads artifacts from a primary source, falling back to a second source for
+/// artifacts that the primary source doesn't have.
+pub struct FallbackArtifacts<P, F>(pub P, pub F);
+
+impl<P, F> ArtifactSource for FallbackArtifacts<P, F>
+where
+    P: ArtifactSource,
+    F: ArtifactSource,
+{
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError> {
+        match self.0.load(name) {
+            Err(ArtifactError::NotFound(_)) => self.1.load(name),
+            Err(ArtifactError::Io(ref err)) if err.kind() == ErrorKind::NotFound => {
+                self.1.load(name)
+            }
+            result => result,
+        }
+    }
+}
+
+/// A truffle project with artifacts in `build/contracts/<Name>.json`.
+pub struct TruffleProject(pub PathBuf);
+
+impl ArtifactSource for TruffleProject {
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError> {
+        Artifact::load(&self.0, name)
+    }
+}
+
+/// A Hardhat project with artifacts in `artifacts/<source>.sol/<Name>.json`
+/// and deployments recorded by `hardhat-deploy` in
+/// `deployments/<network>/<Name>.json`.
+pub struct HardhatProject(pub PathBuf);
+
+impl ArtifactSource for HardhatProject {
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError> {
+        let artifacts = self.0.join("artifacts");
+        let path = artifacts
+            .join("contracts")
+            .join(format!("{}.sol", name))
+            .join(format!("{}.json", name));
+        let path = if path.exists() {
+            path
+        } else {
+            find_artifact(&artifacts, name).ok_or_else(|| ArtifactError::NotFound(name.into()))?
+        };
+
+        let mut artifact: Artifact = serde_json::from_reader(File::open(path)?)?;
+        artifact.networks = hardhat_deployments(&self.0.join("deployments"), name)?;
+
+        Ok(artifact)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HardhatDeployment {
+    address: Address,
+    #[serde(default)]
+    transaction_hash: Option<H256>,
+    #[serde(default)]
+    libraries: HashMap<String, Address>,
+}
+
+/// Reads the deployments of a contract by chain ID, or by network name for
+/// deployments that don't record their chain ID.
+fn hardhat_deployments(
+    deployments: &Path,
+    name: &str,
+) -> Result<HashMap<String, Network>, ArtifactError> {
+    let mut networks = HashMap::new();
+    for dir in subdirectories(deployments)? {
+        let path = dir.join(format!("{}.json", name));
+        if !path.exists() {
+            continue;
+        }
+
+        let deployment: HardhatDeployment = serde_json::from_reader(File::open(path)?)?;
+        let key = match fs::read_to_string(dir.join(".chainId")) {
+            Ok(chain_id) => chain_id.trim().to_string(),
+            Err(_) => file_name(&dir),
+        };
+        networks.insert(
+            key,
+            Network {
+                address: deployment.address,
+                transaction_hash: deployment.transaction_hash,
+                links: deployment.libraries,
+            },
+        );
+    }
+
+    Ok(networks)
+}
+
+/// A Foundry project with artifacts in `out/<source>.sol/<Name>.json` and
+/// deployments recorded by scripts in
+/// `broadcast/<script>.s.sol/<chain ID>/run-latest.json`.
+pub struct FoundryProject(pub PathBuf);
+
+impl ArtifactSource for FoundryProject {
+    fn load(&self, name: &str) -> Result<Artifact, ArtifactError> {
+        let out = self.0.join("out");
+        let path = out
+            .join(format!("{}.sol", name))
+            .join(format!("{}.json", name));
+        let path = if path.exists() {
+            path
+        } else {
+            find_artifact(&out, name).ok_or_else(|| ArtifactError::NotFound(name.into()))?
+        };
+
+        let artifact: FoundryArtifact = serde_json::from_reader(File::open(path)?)?;
+        Ok(Artifact {
+            contract_name: name.to_string(),
+            abi: artifact.abi,
+            bytecode: artifact.bytecode.object,
+            deployed_bytecode: artifact.deployed_bytecode.object,
+            source_map: artifact.bytecode.source_map,
+            deployed_source_map: artifact.deployed_bytecode.source_map,
+            compiler: None,
+            updated_at: None,
+            networks: foundry_deployments(&self.0.join("broadcast"), name)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundryArtifact {
+    abi: ethabi::Contract,
+    bytecode: FoundryBytecode,
+    deployed_bytecode: FoundryBytecode,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundryBytecode {
+    object: String,
+    #[serde(default)]
+    source_map: String,
+}
+
+#[derive(Deserialize)]
+struct FoundryBroadcast {
+    transactions: Vec<FoundryTransaction>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundryTransaction {
+    #[serde(default)]
+    hash: Option<H256>,
+    transaction_type: String,
+    #[serde(default)]
+    contract_name: Option<String>,
+    #[serde(default)]
+    contract_address: Option<Address>,
+}
+
+/// Reads the latest deployments of a contract from the broadcast files of all
+/// scripts, keyed by chain ID.
+fn foundry_deployments(
+    broadcast: &Path,
+    name: &str,
+) -> Result<HashMap<String, Network>, ArtifactError> {
+    let mut networks = HashMap::new();
+    for script in subdirectories(broadcast)? {
+        for chain in subdirectories(&script)? {
+            let path = chain.join("run-latest.json");
+            if !path.exists() {
+                continue;
+            }
+
+            let run: FoundryBroadcast = serde_json::from_reader(File::open(path)?)?;
+            let deployment = run
+                .transactions
+                .into_iter()
+                .filter(|tx| tx.transaction_type == "CREATE" || tx.transaction_type == "CREATE2")
+                .filter(|tx| tx.contract_name.as_ref().map(String::as_str) == Some(name))
+                .filter_map(|tx| Some((tx.contract_address?, tx.hash)))
+                .last();
+            if let Some((address, transaction_hash)) = deployment {
+                networks.insert(
+                    file_name(&chain),
+                    Network {
+                        address,
+                        transaction_hash,
+                        links: HashMap::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(networks)
+}
+
+/// Searches a build output directory for the `<source>.sol/<Name>.json`
+/// artifact of a contract.
+fn find_artifact(dir: &Path, name: &str) -> Option<PathBuf> {
+    let file = format!("{}.json", name);
+    let dirs = subdirectories(dir).ok()?;
+
+    dirs.iter()
+        .filter(|dir| file_name(dir).ends_with(".sol"))
+        .map(|dir| dir.join(&file))
+        .find(|path| path.exists())
+        .or_else(|| {
+            dirs.iter()
+                .filter(|dir| !file_name(dir).ends_with(".sol") && file_name(dir) != "build-info")
+                .filter_map(|dir| find_artifact(dir, name))
+                .next()
+        })
+}
+
+fn subdirectories(dir: &Path) -> Result<Vec<PathBuf>, ArtifactError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch project directory that is removed when dropped.
+    struct Project(PathBuf);
+
+    impl Project {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("scam-ico-{:016x}", rand::random::<u64>()));
+            fs::create_dir_all(&dir).unwrap();
+            Project(dir)
+        }
+
+        fn file(&self, path: &str, contents: &str) -> &Self {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+            self
+        }
+
+        fn artifact(&self, path: &str, bytecode: &str) -> &Self {
+            self.file(
+                path,
+                &format!(
+                    r#"{{ "contractName": "Token", "abi": [], "bytecode": "{}" }}"#,
+                    bytecode
+                ),
+            )
+        }
+
+        fn foundry_artifact(&self, path: &str, bytecode: &str) -> &Self {
+            self.file(
+                path,
+                &format!(
+                    r#"{{
+                        "abi": [],
+                        "bytecode": {{ "object": "{0}" }},
+                        "deployedBytecode": {{ "object": "{0}" }}
+                    }}"#,
+                    bytecode
+                ),
+            )
+        }
+
+        fn detected_bytecode(&self) -> Option<String> {
+            detect(&self.0)
+                .load("Token")
+                .ok()
+                .map(|artifact| artifact.bytecode)
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn address(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    #[test]
+    fn detect_project_kind() {
+        let project = Project::new();
+        project
+            .artifact("build/contracts/Token.json", "0x01")
+            .artifact("artifacts/contracts/Token.sol/Token.json", "0x02")
+            .foundry_artifact("out/Token.sol/Token.json", "0x03");
+
+        // without configuration files, truffle build output takes precedence
+        assert_eq!(project.detected_bytecode(), Some("0x01".into()));
+
+        // configuration files take precedence over any build output
+        project.file("foundry.toml", "");
+        assert_eq!(project.detected_bytecode(), Some("0x03".into()));
+        project.file("hardhat.config.js", "");
+        assert_eq!(project.detected_bytecode(), Some("0x02".into()));
+
+        let project = Project::new();
+        project
+            .file("truffle-config.js", "")
+            .artifact("artifacts/contracts/Token.sol/Token.json", "0x02");
+        assert_eq!(project.detected_bytecode(), None);
+
+        let project = Project::new();
+        project
+            .artifact("artifacts/contracts/Token.sol/Token.json", "0x02")
+            .foundry_artifact("out/Token.sol/Token.json", "0x03");
+        assert_eq!(project.detected_bytecode(), Some("0x02".into()));
+
+        let project = Project::new();
+        project.foundry_artifact("out/Token.sol/Token.json", "0x03");
+        assert_eq!(project.detected_bytecode(), Some("0x03".into()));
+
+        let project = Project::new();
+        match detect(&project.0).load("Token") {
+            Err(ArtifactError::Io(ref err)) if err.kind() == ErrorKind::NotFound => {}
+            result => panic!("unexpected load result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn hardhat_deployments_by_chain_id() {
+        let project = Project::new();
+        project
+            .file("deployments/mainnet/.chainId", "1\n")
+            .file(
+                "deployments/mainnet/Token.json",
+                &format!(
+                    r#"{{ "address": "{:?}", "transactionHash": "{:?}" }}"#,
+                    address(1),
+                    H256::repeat_byte(1)
+                ),
+            )
+            .file(
+                "deployments/localhost/Token.json",
+                &format!(r#"{{ "address": "{:?}" }}"#, address(2)),
+            )
+            .file("deployments/rinkeby/.chainId", "4")
+            .file(
+                "deployments/rinkeby/Other.json",
+                &format!(r#"{{ "address": "{:?}" }}"#, address(3)),
+            );
+
+        let networks = hardhat_deployments(&project.0.join("deployments"), "Token").unwrap();
+
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks["1"].address, address(1));
+        assert_eq!(networks["1"].transaction_hash, Some(H256::repeat_byte(1)));
+        assert_eq!(networks["localhost"].address, address(2));
+        assert_eq!(networks["localhost"].transaction_hash, None);
+    }
+
+    #[test]
+    fn hardhat_deployments_without_directory() {
+        let project = Project::new();
+        let networks = hardhat_deployments(&project.0.join("deployments"), "Token").unwrap();
+        assert!(networks.is_empty());
+    }
+
+    #[test]
+    fn foundry_deployments_latest_create() {
+        let project = Project::new();
+        let transaction = |kind: &str, name: &str, byte: u8| {
+            format!(
+                r#"{{
+                    "hash": "{:?}",
+                    "transactionType": "{}",
+                    "contractName": "{}",
+                    "contractAddress": "{:?}"
+                }}"#,
+                H256::repeat_byte(byte),
+                kind,
+                name,
+                address(byte)
+            )
+        };
+        project
+            .file(
+                "broadcast/Deploy.s.sol/1/run-latest.json",
+                &format!(
+                    r#"{{ "transactions": [{}, {}, {}, {}] }}"#,
+                    transaction("CREATE", "Token", 1),
+                    transaction("CREATE2", "Token", 2),
+                    transaction("CALL", "Token", 3),
+                    transaction("CREATE", "Other", 4)
+                ),
+            )
+            .file(
+                "broadcast/Deploy.s.sol/1/run-1.json",
+                &format!(
+                    r#"{{ "transactions": [{}] }}"#,
+                    transaction("CREATE", "Token", 5)
+                ),
+            )
+            .file(
+                "broadcast/Deploy.s.sol/5/run-latest.json",
+                &format!(
+                    r#"{{ "transactions": [{}] }}"#,
+                    transaction("CREATE", "Other", 6)
+                ),
+            )
+            .file(
+                "broadcast/Upgrade.s.sol/42/run-latest.json",
+                &format!(
+                    r#"{{ "transactions": [{}] }}"#,
+                    transaction("CREATE", "Token", 7)
+                ),
+            );
+
+        let networks = foundry_deployments(&project.0.join("broadcast"), "Token").unwrap();
+
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks["1"].address, address(2));
+        assert_eq!(networks["1"].transaction_hash, Some(H256::repeat_byte(2)));
+        assert_eq!(networks["42"].address, address(7));
+    }
+
+    #[test]
+    fn find_artifact_recursively() {
+        let project = Project::new();
+        project
+            .artifact("artifacts/build-info/Token.sol/Token.json", "0x01")
+            .artifact("artifacts/contracts/tokens/Token.sol/Token.json", "0x02")
+            .artifact("artifacts/contracts/Other.sol/Other.json", "0x03");
+
+        let artifacts = project.0.join("artifacts");
+        assert_eq!(
+            find_artifact(&artifacts, "Token"),
+            Some(artifacts.join("contracts/tokens/Token.sol/Token.json"))
+        );
+        assert_eq!(find_artifact(&artifacts, "Missing"), None);
+        assert_eq!(find_artifact(&project.0.join("missing"), "Token"), None);
+    }
+
+    #[test]
+    fn find_artifact_skips_build_info() {
+        let project = Project::new();
+        project.artifact("artifacts/build-info/Token.sol/Token.json", "0x01");
+
+        assert_eq!(find_artifact(&project.0.join("artifacts"), "Token"), None);
+    }
+}
@@ -0,0 +1,113 @@
+//! Solidity type mapping for the contract bindings generated by the build
+//! script. It is included by `build.rs` and only compiled into the crate for
+//! its tests.
+
+/// Maps a Solidity type to the Rust type used in the bindings and the ethabi
+/// parameter type expression. Signed integers are not supported since web3's
+/// tokens have no signed integer type, so they would be silently read and
+/// written as unsigned values.
+pub fn param_type(solidity: &str) -> Option<(&'static str, String)> {
+    match canonical_type(solidity).as_str() {
+        "address" => Some(("web3::types::Address", "ethabi::ParamType::Address".into())),
+        "bool" => Some(("bool", "ethabi::ParamType::Bool".into())),
+        "string" => Some(("String", "ethabi::ParamType::String".into())),
+        "bytes" => Some(("Vec<u8>", "ethabi::ParamType::Bytes".into())),
+        "bytes32" => Some((
+            "web3::types::H256",
+            "ethabi::ParamType::FixedBytes(32)".into(),
+        )),
+        solidity if solidity.starts_with("uint") => {
+            let bits = solidity["uint".len()..].parse::<usize>().ok()?;
+            if bits == 0 || bits > 256 || bits % 8 != 0 {
+                return None;
+            }
+            Some((
+                "web3::types::U256",
+                format!("ethabi::ParamType::Uint({})", bits),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Expands the `uint` and `int` aliases, which are not allowed in function
+/// signatures.
+pub fn canonical_type(solidity: &str) -> String {
+    match solidity {
+        "uint" | "int" => format!("{}256", solidity),
+        _ => solidity.to_string(),
+    }
+}
+
+/// Formats the signature of a function used to compute its selector, for
+/// example `transfer(address,uint256)`.
+pub fn signature(name: &str, types: &[&str]) -> String {
+    let types: Vec<_> = types.iter().map(|kind| canonical_type(kind)).collect();
+    format!("{}({})", name, types.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hex::ToHex;
+
+    #[test]
+    fn supported_param_types() {
+        assert_eq!(
+            param_type("address"),
+            Some(("web3::types::Address", "ethabi::ParamType::Address".into()))
+        );
+        assert_eq!(
+            param_type("uint8"),
+            Some(("web3::types::U256", "ethabi::ParamType::Uint(8)".into()))
+        );
+        assert_eq!(
+            param_type("uint"),
+            Some(("web3::types::U256", "ethabi::ParamType::Uint(256)".into()))
+        );
+        assert_eq!(
+            param_type("bytes32"),
+            Some((
+                "web3::types::H256",
+                "ethabi::ParamType::FixedBytes(32)".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn unsupported_param_types() {
+        for solidity in &[
+            "int",
+            "int8",
+            "int256",
+            "uint7",
+            "uint0",
+            "uint264",
+            "uintx",
+            "bytes4",
+            "uint256[]",
+            "tuple",
+        ] {
+            assert_eq!(param_type(solidity), None, "{}", solidity);
+        }
+    }
+
+    #[test]
+    fn function_signatures() {
+        let selector = |name, types: &[&str]| {
+            tiny_keccak::keccak256(signature(name, types).as_bytes())[..4].to_hex::<String>()
+        };
+
+        assert_eq!(
+            signature("transfer", &["address", "uint"]),
+            "transfer(address,uint256)"
+        );
+        assert_eq!(selector("transfer", &["address", "uint"]), "a9059cbb");
+        assert_eq!(selector("balanceOf", &["address"]), "70a08231");
+        assert_eq!(
+            selector("transferFrom", &["address", "address", "uint256"]),
+            "23b872dd"
+        );
+        assert_eq!(selector("decimals", &[]), "313ce567");
+    }
+}
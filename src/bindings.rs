@@ -0,0 +1,6 @@
+//! Typed contract bindings generated from the contract ABIs by the build
+//! script, see `build.rs`.
+
+#![allow(dead_code)]
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
@@ -0,0 +1,335 @@
+use ethsign::SecretKey;
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Secp256k1, SecretKey as Secp256k1SecretKey};
+use sha2::Sha512;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl FromStr for DerivationPath {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = s.split('/');
+        if components.next() != Some("m") {
+            return Err(Bip32Error::InvalidPath(s.to_string()));
+        }
+
+        let indices = components
+            .map(|component| {
+                let (index, offset) = if component.ends_with('\'') {
+                    (&component[..component.len() - 1], HARDENED)
+                } else {
+                    (component, 0)
+                };
+                match index.parse::<u32>() {
+                    Ok(i) if i < HARDENED => Ok(i + offset),
+                    _ => Err(Bip32Error::InvalidPath(s.to_string())),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(DerivationPath(indices))
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if index & HARDENED != 0 {
+                write!(f, "/{}'", index & !HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate(String);
+
+impl PathTemplate {
+    pub const BIP44: &'static str = "m/44'/60'/0'/0/{i}";
+    pub const LEGACY: &'static str = "m/44'/60'/0'/{i}";
+    pub const LEDGER_LIVE: &'static str = "m/44'/60'/{i}'/0/0";
+
+    pub fn path(&self, index: u32) -> Result<DerivationPath, Bip32Error> {
+        self.0.replace("{i}", &index.to_string()).parse()
+    }
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        PathTemplate(PathTemplate::BIP44.to_string())
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let template = match s {
+            "bip44" => PathTemplate::BIP44,
+            "legacy" => PathTemplate::LEGACY,
+            "ledger-live" => PathTemplate::LEDGER_LIVE,
+            custom if custom.matches("{i}").count() == 1 => custom,
+            _ => return Err(Bip32Error::InvalidTemplate(s.to_string())),
+        };
+
+        // make sure the template expands to a valid path before accepting it
+        let template = PathTemplate(template.to_string());
+        template.path(0)?;
+
+        Ok(template)
+    }
+}
+
+impl Display for PathTemplate {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Clone)]
+pub struct ExtendedPrivateKey {
+    key: Secp256k1SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedPrivateKey {
+    pub fn master(seed: &[u8]) -> Result<ExtendedPrivateKey, Bip32Error> {
+        let digest = hmac_sha512(b"Bitcoin seed", &[seed]);
+        let key =
+            Secp256k1SecretKey::from_slice(&digest[..32]).map_err(|_| Bip32Error::InvalidKey)?;
+        let mut chain_code = [0; 32];
+        chain_code.copy_from_slice(&digest[32..]);
+
+        Ok(ExtendedPrivateKey { key, chain_code })
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<ExtendedPrivateKey, Bip32Error> {
+        path.0
+            .iter()
+            .try_fold(self.clone(), |key, &index| key.child(index))
+    }
+
+    pub fn child(&self, index: u32) -> Result<ExtendedPrivateKey, Bip32Error> {
+        let digest = if index & HARDENED != 0 {
+            hmac_sha512(
+                &self.chain_code,
+                &[&[0], &self.key[..], &index.to_be_bytes()],
+            )
+        } else {
+            let secp = Secp256k1::signing_only();
+            let public = PublicKey::from_secret_key(&secp, &self.key).serialize();
+            hmac_sha512(&self.chain_code, &[&public, &index.to_be_bytes()])
+        };
+
+        let mut key = self.key;
+        key.add_assign(&digest[..32])
+            .map_err(|_| Bip32Error::InvalidKey)?;
+        let mut chain_code = [0; 32];
+        chain_code.copy_from_slice(&digest[32..]);
+
+        Ok(ExtendedPrivateKey { key, chain_code })
+    }
+
+    pub fn secret(&self) -> Result<SecretKey, Bip32Error> {
+        SecretKey::from_raw(&self.key[..]).map_err(|_| Bip32Error::InvalidKey)
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_varkey(key).expect("HMAC accepts keys of any length");
+    for chunk in data {
+        mac.input(chunk);
+    }
+
+    let mut digest = [0; 64];
+    digest.copy_from_slice(&mac.result().code());
+    digest
+}
+
+#[derive(Debug, Error)]
+pub enum Bip32Error {
+    #[error("invalid derivation path '{0}'")]
+    InvalidPath(String),
+
+    #[error("invalid derivation path template '{0}', expected 'bip44', 'legacy', 'ledger-live' or a path with a single '{{i}}' placeholder")]
+    InvalidTemplate(String),
+
+    #[error("derived key is not a valid secp256k1 secret key")]
+    InvalidKey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bip39::{Language, Mnemonic, Seed};
+    use rustc_hex::{FromHex, ToHex};
+
+    fn check_chain(seed: &str, chain: &[(&str, &str, &str)]) {
+        let seed: Vec<u8> = seed.from_hex().unwrap();
+        let master = ExtendedPrivateKey::master(&seed).unwrap();
+        for &(path, chain_code, key) in chain {
+            let derived = master.derive(&path.parse().unwrap()).unwrap();
+            assert_eq!(
+                derived.chain_code.to_hex::<String>(),
+                chain_code,
+                "{}",
+                path
+            );
+            assert_eq!(derived.key[..].to_hex::<String>(), key, "{}", path);
+        }
+    }
+
+    #[test]
+    fn bip32_test_vector_1() {
+        check_chain(
+            "000102030405060708090a0b0c0d0e0f",
+            &[
+                (
+                    "m",
+                    "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+                    "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+                ),
+                (
+                    "m/0'",
+                    "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+                    "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+                ),
+                (
+                    "m/0'/1",
+                    "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+                    "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+                ),
+                (
+                    "m/0'/1/2'",
+                    "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f",
+                    "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+                ),
+                (
+                    "m/0'/1/2'/2",
+                    "cfb71883f01676f587d023cc53a35bc7f88f724b1f8c2892ac1275ac822a3edd",
+                    "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4",
+                ),
+                (
+                    "m/0'/1/2'/2/1000000000",
+                    "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e",
+                    "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn bip32_test_vector_2() {
+        check_chain(
+            "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542",
+            &[
+                (
+                    "m",
+                    "60499f801b896d83179a4374aeb7822aaeaceaa0db1f85ee3e904c4defbd9689",
+                    "4b03d6fc340455b363f51020ad3ecca4f0850280cf436c70c727923f6db46c3e",
+                ),
+                (
+                    "m/0",
+                    "f0909affaa7ee7abe5dd4e100598d4dc53cd709d5a5c2cac40e7412f232f7c9c",
+                    "abe74a98f6c7eabee0428f53798f0ab8aa1bd37873999041703c742f15ac7e1e",
+                ),
+                (
+                    "m/0/2147483647'",
+                    "be17a268474a6bb9c61e1d720cf6215e2a88c5406c4aee7b38547f585c9a37d9",
+                    "877c779ad9687164e9c2f4f0f4ff0340814392330693ce95a58fe18fd52e6e93",
+                ),
+                (
+                    "m/0/2147483647'/1",
+                    "f366f48f1ea9f2d1d3fe958c95ca84ea18e4c4ddb9366c336c927eb246fb38cb",
+                    "704addf544a06e5ee4bea37098463c23613da32020d604506da8c0518e1da4b7",
+                ),
+                (
+                    "m/0/2147483647'/1/2147483646'",
+                    "637807030d55d01f9a0cb3a7839515d796bd07706386a6eddf06cc29a65a0e29",
+                    "f1c7c871a54a804afe328b4c83a1c33b8e5ff48f5087273f04efa83b247d6a2d",
+                ),
+                (
+                    "m/0/2147483647'/1/2147483646'/2",
+                    "9452b549be8cea3ecb7a84bec10dcfd94afe4d129ebfd3b3cb58eedf394ed271",
+                    "bb7d39bdb83ecf58f2fd82b6d918341cbef428661ef01ab97c28a4842125ac23",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn bip44_ethereum_account() {
+        let mnemonic = Mnemonic::from_phrase(
+            "test test test test test test test test test test test junk",
+            Language::English,
+        )
+        .unwrap();
+        let seed = Seed::new(&mnemonic, "");
+        let secret = ExtendedPrivateKey::master(seed.as_bytes())
+            .unwrap()
+            .derive(&"m/44'/60'/0'/0/0".parse().unwrap())
+            .unwrap()
+            .secret()
+            .unwrap();
+
+        assert_eq!(
+            secret.public().address().to_hex::<String>(),
+            "f39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+        );
+    }
+
+    #[test]
+    fn derivation_path_round_trip() {
+        for path in &["m", "m/0", "m/44'/60'/0'/0/0", "m/2147483647'"] {
+            assert_eq!(path.parse::<DerivationPath>().unwrap().to_string(), *path);
+        }
+        for path in &["", "0/1", "m/", "m/x", "m/2147483648", "m/1''"] {
+            assert!(path.parse::<DerivationPath>().is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn path_template_expansion() {
+        let expand = |template: &str, index| {
+            template
+                .parse::<PathTemplate>()
+                .unwrap()
+                .path(index)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(expand("bip44", 3), "m/44'/60'/0'/0/3");
+        assert_eq!(expand("legacy", 3), "m/44'/60'/0'/3");
+        assert_eq!(expand("ledger-live", 3), "m/44'/60'/3'/0/0");
+        assert_eq!(expand("m/1'/{i}'/2", 7), "m/1'/7'/2");
+        assert_eq!(
+            PathTemplate::default().path(0).unwrap().to_string(),
+            "m/44'/60'/0'/0/0"
+        );
+    }
+
+    #[test]
+    fn path_template_rejects_invalid() {
+        for template in &[
+            "m/44'/60'/0'/0/0",
+            "m/{i}/{i}",
+            "44'/{i}",
+            "m/{i}x",
+            "bip32",
+        ] {
+            assert!(template.parse::<PathTemplate>().is_err(), "{}", template);
+        }
+    }
+}
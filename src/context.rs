@@ -1,54 +1,148 @@
-use crate::contract::Contract;
+use crate::activity::{Activity, ActivityKind};
+use crate::amount::{Amount, AmountError};
+use crate::artifacts::ArtifactSource;
+use crate::bindings::{MagicWeth, Scam, ScamIco};
+use crate::contract::{
+    self, AccessListEstimate, Contract, ContractTransactionBuilder, DecodeError, DeployError,
+    GasPricing, NetworkError, SendError, SignedTransaction,
+};
+use crate::events::Transfer;
+use crate::revert::{self, RevertReason};
+use crate::transaction::{self, TransactionOutcome, WaitError};
 use crate::truffle::{Artifact, ArtifactError};
-use std::path::{Path, PathBuf};
+use crate::wallet::{Account, WalletError};
+use ethabi::{Token, Topic};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::Error as IoError;
+use std::time::Duration;
 use thiserror::Error;
 use web3::contract::Error as Web3ContractError;
 use web3::error::Error as Web3Error;
 use web3::futures::future::{self, Either};
 use web3::futures::Future;
-use web3::types::{Address, U256};
+use web3::types::{Address, BlockNumber, U256};
 use web3::{Transport, Web3};
 
+/// The amount of WETH the ICO raises, as hard-coded in the ScamIco contract.
+pub const ICO_TARGET: u64 = 100;
+
+/// The amount of SCM minted per contributed WETH on claim.
+pub const ICO_RATE: u64 = 10;
+
 pub struct Context<T: Transport> {
     pub web3: Web3<T>,
-    pub ico: Contract<T>,
-    pub weth: Contract<T>,
-    pub scm: Contract<T>,
+    pub ico: ScamIco<T>,
+    pub weth: MagicWeth<T>,
+    pub scm: Scam<T>,
+    pub chain_id: u64,
+    pub confirmations: usize,
+    pub timeout: Duration,
+    pub gas: GasOptions,
+    pub access_lists: bool,
 }
 
-impl<T: Transport> Context<T> {
-    pub fn new<P>(
-        web3: Web3<T>,
-        truffle_project: P,
-    ) -> impl Future<Item = Context<T>, Error = ContextError>
+/// How the transactions sent by the context pay for gas. Prices and fees that
+/// are not set are estimated by the node.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GasOptions {
+    /// The gas price of legacy transactions.
+    pub gas_price: Option<U256>,
+    /// Sends EIP-1559 transactions, which setting either fee implies.
+    pub eip1559: bool,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl GasOptions {
+    fn apply<T>(self, tx: ContractTransactionBuilder<T>) -> ContractTransactionBuilder<T>
     where
-        P: AsRef<Path>,
+        T: Transport,
     {
-        Context::with_ico_contract_factory(web3, truffle_project, |web3, artifact| {
-            Contract::new(web3, artifact).map_err(Into::into)
+        tx.gas_price(self.gas_price)
+            .eip1559(self.eip1559)
+            .max_fee_per_gas(self.max_fee_per_gas)
+            .max_priority_fee_per_gas(self.max_priority_fee_per_gas)
+    }
+}
+
+impl<T: Transport> Context<T> {
+    /// Loads the context for the ICO deployment recorded in the ScamIco
+    /// artifact, either for the specified network key or for the network the
+    /// node is connected to.
+    pub fn new(
+        web3: Web3<T>,
+        artifacts: &dyn ArtifactSource,
+        network: Option<String>,
+    ) -> impl Future<Item = Context<T>, Error = ContextError> {
+        Context::with_ico_contract_factory(web3, artifacts, move |web3, artifact| {
+            use Either::*;
+
+            match network {
+                Some(network) => A(future::result(
+                    Contract::on_network(web3, artifact, &network).map_err(Into::into),
+                )),
+                None => B(Contract::new(web3, artifact).map_err(Into::into)),
+            }
         })
     }
 
-    pub fn with_ico_address<P>(
+    pub fn with_ico_address(
         web3: Web3<T>,
-        truffle_project: P,
+        artifacts: &dyn ArtifactSource,
         address: Address,
-    ) -> impl Future<Item = Context<T>, Error = ContextError>
-    where
-        P: AsRef<Path>,
-    {
-        Context::with_ico_contract_factory(web3, truffle_project, move |web3, artifact| {
+    ) -> impl Future<Item = Context<T>, Error = ContextError> {
+        Context::with_ico_contract_factory(web3, artifacts, move |web3, artifact| {
             future::ok(Contract::at(web3, address, artifact))
         })
     }
 
-    fn with_ico_contract_factory<P, R, F>(
+    /// Deploys a new Scam ICO contract for the specified WETH contract from an
+    /// account and loads the context for it.
+    pub fn deploy(
+        web3: Web3<T>,
+        artifacts: &dyn ArtifactSource,
+        weth: Address,
+        account: &Account,
+        gas: GasOptions,
+    ) -> impl Future<Item = Context<T>, Error = ContextError> {
+        let account = account.clone();
+        Context::with_ico_contract_factory(web3, artifacts, move |web3, artifact| {
+            use Either::*;
+
+            if account.is_watch_only() {
+                return A(future::err(ContextError::WatchOnly(account.address())));
+            }
+
+            let deploy = future::result(Contract::deploy(web3.clone(), artifact, weth))
+                .map_err(ContextError::from)
+                .map(move |deploy| {
+                    deploy
+                        .gas_price(gas.gas_price)
+                        .eip1559(gas.eip1559)
+                        .max_fee_per_gas(gas.max_fee_per_gas)
+                        .max_priority_fee_per_gas(gas.max_priority_fee_per_gas)
+                });
+            B(contract::chain_id(&web3)
+                .map_err(ContextError::from)
+                .join(deploy)
+                .and_then(move |(chain_id, deploy)| {
+                    match account.secret() {
+                        Some(secret) => deploy.sign(Some(secret.clone()), Some(chain_id)),
+                        None => deploy.from(account.address()),
+                    }
+                    .send()
+                    .map_err(ContextError::from)
+                }))
+        })
+    }
+
+    fn with_ico_contract_factory<R, F>(
         web3: Web3<T>,
-        truffle_project: P,
+        artifacts: &dyn ArtifactSource,
         factory: F,
     ) -> impl Future<Item = Context<T>, Error = ContextError>
     where
-        P: AsRef<Path>,
         R: Future<Item = Contract<T>, Error = ContextError>,
         F: FnOnce(Web3<T>, Artifact) -> R,
     {
@@ -62,50 +156,171 @@ impl<T: Transport> Context<T> {
             };
         }
 
-        // create an owned copy of our truffle project path so we can move it
-        // through our futures chain
-        let tp = PathBuf::from(truffle_project.as_ref());
-
-        let ico_artifact = try_result!(Artifact::load(&tp, "ScamIco"));
+        // load all artifacts up front so that the artifact source doesn't need
+        // to be moved through our futures chain
+        let ico_artifact = try_result!(artifacts.load("ScamIco"));
+        let weth_artifact = try_result!(artifacts.load("WETH9"));
+        let scm_artifact = try_result!(artifacts.load("Scam"));
         A(factory(web3.clone(), ico_artifact)
             .and_then(move |ico| {
-                let weth_artifact = try_result!(Artifact::load(&tp, "WETH9"));
-                A(ico
-                    .call("weth", ())
+                let ico = ScamIco::new(ico);
+                ico.weth()
                     .map_err(Into::into)
                     .and_then(move |weth_address| {
                         let weth = Contract::at(web3.clone(), weth_address, weth_artifact);
-                        Ok((tp, web3, ico, weth))
-                    }))
+                        Ok((web3, ico, MagicWeth::new(weth)))
+                    })
             })
-            .and_then(|(tp, web3, ico, weth)| {
-                let scm_artifact = try_result!(Artifact::load(&tp, "Scam"));
-                A(ico
-                    .call("scm", ())
-                    .map_err(Into::into)
-                    .and_then(move |scm_address| {
-                        let scm = Contract::at(web3.clone(), scm_address, scm_artifact);
-                        Ok(Context {
-                            web3,
-                            ico,
-                            weth,
-                            scm,
-                        })
-                    }))
+            .and_then(move |(web3, ico, weth)| {
+                ico.scm().map_err(Into::into).and_then(move |scm_address| {
+                    let scm = Contract::at(web3.clone(), scm_address, scm_artifact);
+                    Ok((web3, ico, weth, Scam::new(scm)))
+                })
+            })
+            .and_then(|(web3, ico, weth, scm)| {
+                contract::chain_id(&web3)
+                    .map_err(ContextError::from)
+                    .map(move |chain_id| Context {
+                        web3,
+                        ico,
+                        weth,
+                        scm,
+                        chain_id,
+                        confirmations: 0,
+                        timeout: transaction::DEFAULT_TIMEOUT,
+                        gas: GasOptions::default(),
+                        access_lists: false,
+                    })
             }))
     }
 
+    /// Verifies that the ICO, WETH and SCM contracts have the code of the
+    /// ScamIco, WETH9 or MagicWeth, and Scam artifacts respectively, returning
+    /// the contracts that don't or that can't be verified since the artifacts
+    /// have no deployed bytecode.
+    pub fn verify_code(
+        &self,
+        artifacts: &dyn ArtifactSource,
+    ) -> impl Future<Item = Vec<CodeMismatch>, Error = ContextError> {
+        let load = |names: &[&'static str]| -> Result<Vec<_>, ArtifactError> {
+            names
+                .iter()
+                .map(|&name| Ok((name, artifacts.load(name)?)))
+                .collect()
+        };
+        let checks = || -> Result<_, ArtifactError> {
+            Ok(vec![
+                ("ICO", self.ico.address(), load(&["ScamIco"])?),
+                ("WETH", self.weth.address(), load(&["WETH9", "MagicWeth"])?),
+                ("SCM", self.scm.address(), load(&["Scam"])?),
+            ])
+        };
+
+        let eth = self.web3.eth();
+        future::result(checks())
+            .map_err(ContextError::from)
+            .and_then(move |checks| {
+                future::join_all(
+                    checks
+                        .into_iter()
+                        .map(move |(contract, address, artifacts)| {
+                            eth.code(address, None)
+                                .map_err(ContextError::from)
+                                .map(move |code| check_code(contract, address, artifacts, &code.0))
+                        }),
+                )
+            })
+            .map(|mismatches| mismatches.into_iter().flatten().collect())
+    }
+
+    /// Sets the number of confirmations to wait for after a transaction is
+    /// mined before its outcome is reported.
+    pub fn confirmations(mut self, confirmations: usize) -> Context<T> {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Sets how long to wait for a transaction to be mined and confirmed before
+    /// giving up.
+    pub fn timeout(mut self, timeout: Duration) -> Context<T> {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how the transactions sent by the context pay for gas.
+    pub fn gas_options(mut self, gas: GasOptions) -> Context<T> {
+        self.gas = gas;
+        self
+    }
+
+    /// Sets whether funding transactions are sent with an EIP-2930 access list
+    /// generated with `eth_createAccessList`.
+    pub fn access_lists(mut self, access_lists: bool) -> Context<T> {
+        self.access_lists = access_lists;
+        self
+    }
+
+    /// Subscribes to the WETH deposits, WETH approvals and contributions to
+    /// the ICO, and SCM mints that make up the activity feed.
+    pub fn activity(&self) -> impl Future<Item = Activity<T>, Error = ContextError> {
+        let ico = Topic::This(Token::Address(self.ico.address()));
+        let zero = Topic::This(Token::Address(Address::zero()));
+        let events = || -> Result<_, Web3ContractError> {
+            Ok(vec![
+                (
+                    ActivityKind::Deposit,
+                    self.weth.contract().events("Deposit")?,
+                ),
+                (
+                    ActivityKind::Approval,
+                    self.weth.contract().events("Approval")?.topic1(ico.clone()),
+                ),
+                (
+                    ActivityKind::Contribution,
+                    self.weth.contract().events("Transfer")?.topic1(ico),
+                ),
+                (
+                    ActivityKind::Mint,
+                    self.scm.contract().events("Transfer")?.topic0(zero),
+                ),
+            ])
+        };
+
+        future::result(events())
+            .and_then(|events| {
+                future::join_all(events.into_iter().map(|(kind, events)| {
+                    events
+                        .subscribe()
+                        .map(move |subscription| (kind, subscription))
+                }))
+            })
+            .map(Activity::new)
+            .map_err(ContextError::from)
+    }
+
+    /// Rebuilds the contributions to the ICO from the WETH transfers to it,
+    /// ordered from largest to smallest contributor.
+    pub fn contributors(&self) -> impl Future<Item = Vec<Contributor>, Error = ContextError> {
+        let ico = Topic::This(Token::Address(self.ico.address()));
+
+        future::result(self.weth.contract().events("Transfer"))
+            .map_err(Web3ContractError::from)
+            .and_then(|events| events.topic1(ico).query_typed::<Transfer>())
+            .map(|transfers| contributors(transfers.into_iter().map(|transfer| transfer.data)))
+            .map_err(ContextError::from)
+    }
+
     pub fn state(&self) -> impl Future<Item = State, Error = ContextError> {
         use Either::*;
 
         let ico = self.ico.clone();
-        ico.call::<_, _, U256>("state", ())
+        ico.state()
             .map_err(ContextError::from)
             .and_then(move |state| {
                 if state == U256::from(0) {
                     A(ico
-                        .call("remaining", ())
-                        .map(|balance| State::Funding(u256_to_f64_amount(balance, 18)))
+                        .remaining()
+                        .map(|balance| State::Funding(Amount::new(balance, 18)))
                         .map_err(Into::into))
                 } else if state == U256::from(1) {
                     B(A(future::ok(State::Closed)))
@@ -117,136 +332,412 @@ impl<T: Transport> Context<T> {
             })
     }
 
-    pub fn claim(&self, account: Address) -> impl Future<Item = (), Error = ContextError> {
-        self.ico
-            .function("claim", ())
-            .from(account)
-            .send()
-            .map(|_| ())
-            .map_err(Into::into)
+    /// Claims SCM tokens for the account. The contract's requirements are
+    /// checked and the claim is simulated before it is sent.
+    pub fn claim(
+        &self,
+        account: &Account,
+    ) -> impl Future<Item = TransactionOutcome, Error = ContextError> {
+        let ico = self.ico.clone();
+        let account = account.clone();
+        let (chain_id, confirmations, timeout, gas) =
+            (self.chain_id, self.confirmations, self.timeout, self.gas);
+
+        self.state()
+            .join(
+                ico.contributions(account.address())
+                    .map_err(ContextError::from),
+            )
+            .and_then(|(state, contribution)| match state {
+                State::Finished if contribution.is_zero() => Err(ContextError::NothingToClaim),
+                State::Finished => Ok(()),
+                _ => Err(ContextError::ClaimNotReady),
+            })
+            .and_then(move |_| preflight(ico.claim(), &account).map(move |_| (ico, account)))
+            .and_then(move |(ico, account)| {
+                send_from(ico.claim(), &account, chain_id, confirmations, timeout, gas)
+            })
     }
 
     pub fn balances(
         &self,
         account: Address,
-    ) -> impl Future<Item = (f64, f64, f64, f64), Error = ContextError> {
+    ) -> impl Future<Item = (Amount, Amount, Amount, Amount), Error = ContextError> {
         Future::join4(
             self.web3
                 .eth()
                 .balance(account, None)
-                .map(|balance| u256_to_f64_amount(balance, 18))
+                .map(|balance| Amount::new(balance, 18))
                 .map_err(Into::into),
-            erc20_balance(self.weth.clone(), account),
+            erc20_balance(self.weth.decimals(), self.weth.balance_of(account)),
             self.ico
-                .call("contributions", account)
-                .map(|balance| u256_to_f64_amount(balance, 18))
+                .contributions(account)
+                .map(|balance| Amount::new(balance, 18))
                 .map_err(Into::into),
-            erc20_balance(self.scm.clone(), account),
+            erc20_balance(self.scm.decimals(), self.scm.balance_of(account)),
         )
     }
 
+    pub fn is_used(&self, account: Address) -> impl Future<Item = bool, Error = ContextError> {
+        Future::join3(
+            has_activity(&self.web3, account),
+            self.weth.balance_of(account).map_err(ContextError::from),
+            self.scm.balance_of(account).map_err(ContextError::from),
+        )
+        .map(|(active, weth, scm)| active || !weth.is_zero() || !scm.is_zero())
+    }
+
     pub fn purchase_weth(
         &self,
-        account: Address,
-        amount: f64,
-    ) -> impl Future<Item = (), Error = ContextError> {
+        account: &Account,
+        amount: Amount,
+    ) -> impl Future<Item = TransactionOutcome, Error = ContextError> {
         let weth = self.weth.clone();
-        weth.call::<_, _, U256>("decimals", ())
-            .map(|decimals| decimals.as_u32() as i32)
+        let account = account.clone();
+        let (chain_id, confirmations, timeout, gas) =
+            (self.chain_id, self.confirmations, self.timeout, self.gas);
+        weth.decimals()
             .map_err(ContextError::from)
             .and_then(move |decimals| {
-                let amount = f64_amount_to_u256(amount, decimals);
-                weth.function("deposit", ())
-                    .value(Some(amount))
-                    .from(account)
-                    .send()
-                    .map(|_| ())
+                Amount::checked_decimals(decimals)
+                    .and_then(|decimals| amount.rescale(decimals))
                     .map_err(ContextError::from)
             })
+            .and_then(move |amount| {
+                let amount = amount.raw();
+                send_from(
+                    weth.deposit().value(Some(amount)),
+                    &account,
+                    chain_id,
+                    confirmations,
+                    timeout,
+                    gas,
+                )
+            })
     }
 
     pub fn magic_weth(
         &self,
-        account: Address,
-        amount: f64,
-    ) -> impl Future<Item = (), Error = ContextError> {
+        account: &Account,
+        amount: Amount,
+    ) -> impl Future<Item = TransactionOutcome, Error = ContextError> {
         let weth = self.weth.clone();
-        weth.call::<_, _, U256>("decimals", ())
-            .map(|decimals| decimals.as_u32() as i32)
+        let account = account.clone();
+        let (chain_id, confirmations, timeout, gas) =
+            (self.chain_id, self.confirmations, self.timeout, self.gas);
+        weth.decimals()
             .map_err(ContextError::from)
             .and_then(move |decimals| {
-                let amount = f64_amount_to_u256(amount, decimals);
-                weth.function("magicallyCreate", (account, amount))
-                    .from(account)
-                    .send()
-                    .map(|_| ())
+                Amount::checked_decimals(decimals)
+                    .and_then(|decimals| amount.rescale(decimals))
                     .map_err(ContextError::from)
             })
+            .and_then(move |amount| {
+                let amount = amount.raw();
+                send_from(
+                    weth.magically_create(account.address(), amount),
+                    &account,
+                    chain_id,
+                    confirmations,
+                    timeout,
+                    gas,
+                )
+            })
     }
 
+    /// Funds the ICO with WETH from the account. The contract's requirements
+    /// are checked before approving the ICO to transfer the WETH, the approval
+    /// is skipped when the allowance is already sufficient, and the funding is
+    /// simulated before it is sent. When access lists are enabled, the funding
+    /// is sent with a generated access list.
     pub fn fund(
         &self,
-        account: Address,
-        amount: f64,
-    ) -> impl Future<Item = (), Error = ContextError> {
+        account: &Account,
+        amount: Amount,
+    ) -> impl Future<Item = FundOutcome, Error = ContextError> {
         let ico = self.ico.clone();
         let weth = self.weth.clone();
+        let account = account.clone();
+        let (chain_id, confirmations, timeout, gas) =
+            (self.chain_id, self.confirmations, self.timeout, self.gas);
+        let access_lists = self.access_lists;
 
-        weth.call::<_, _, U256>("decimals", ())
-            .map(|decimals| decimals.as_u32() as i32)
+        weth.decimals()
             .map_err(ContextError::from)
             .and_then(move |decimals| {
-                let amount = f64_amount_to_u256(amount, decimals);
-                weth.function("approve", (ico.address(), amount))
-                    .from(account)
-                    .send()
-                    .map(move |_| (ico, account, amount))
+                Amount::checked_decimals(decimals)
+                    .and_then(|decimals| amount.rescale(decimals))
                     .map_err(ContextError::from)
             })
-            .and_then(|(ico, account, amount)| {
-                ico.function("fund", amount)
-                    .from(account)
-                    .send()
-                    .map(|_| ())
+            .join(self.state())
+            .and_then(|(amount, state)| match state {
+                State::Funding(remaining) if amount.raw() > remaining.raw() => {
+                    Err(ContextError::AmountTooLarge { amount, remaining })
+                }
+                State::Funding(_) => Ok(amount),
+                _ => Err(ContextError::IcoClosed),
+            })
+            .and_then(move |amount| {
+                let owner = account.address();
+                weth.balance_of(owner)
+                    .join(weth.allowance(owner, ico.address()))
                     .map_err(ContextError::from)
+                    .map(move |(balance, allowance)| {
+                        (weth, ico, account, amount, balance, allowance)
+                    })
+            })
+            .and_then(move |(weth, ico, account, amount, balance, allowance)| {
+                use Either::*;
+
+                if balance < amount.raw() {
+                    let balance = Amount::new(balance, amount.decimals());
+                    return A(future::err(ContextError::InsufficientBalance {
+                        balance,
+                        amount,
+                    }));
+                }
+
+                let approval = if allowance >= amount.raw() {
+                    A(future::ok(()))
+                } else {
+                    B(send_from(
+                        weth.approve(ico.address(), amount.raw()),
+                        &account,
+                        chain_id,
+                        confirmations,
+                        timeout,
+                        gas,
+                    )
+                    .map(|_| ()))
+                };
+                B(approval.map(move |_| (ico, account, amount)))
+            })
+            .and_then(|(ico, account, amount)| {
+                preflight(ico.fund(amount.raw()), &account).map(move |_| (ico, account, amount))
+            })
+            .and_then(move |(ico, account, amount)| {
+                use Either::*;
+
+                let estimate = if access_lists {
+                    A(gas
+                        .apply(ico.fund(amount.raw()).from(account.address()))
+                        .estimate_access_list()
+                        .map(Some)
+                        .map_err(ContextError::from))
+                } else {
+                    B(future::ok(None))
+                };
+                estimate.map(move |estimate| (ico, account, amount, estimate))
+            })
+            .and_then(move |(ico, account, amount, estimate)| {
+                let access_list = estimate
+                    .as_ref()
+                    .map(|estimate| estimate.access_list.clone());
+                send_from(
+                    ico.fund(amount.raw()).access_list(access_list),
+                    &account,
+                    chain_id,
+                    confirmations,
+                    timeout,
+                    gas,
+                )
+                .map(move |transaction| FundOutcome {
+                    transaction,
+                    access_list: estimate,
+                })
             })
     }
 }
 
-fn erc20_balance<T>(
-    token: Contract<T>,
+/// Decodes a signed raw transaction, recovering its sender and decoding its
+/// calldata against the ICO, WETH and SCM contract ABIs, so that it can be
+/// audited before it is broadcast. No node is needed, so calls are reported
+/// with the ABIs that have a matching function regardless of the address they
+/// are sent to.
+pub fn decode_transaction<A>(raw: &[u8], artifacts: &A) -> Result<DecodedTransaction, ContextError>
+where
+    A: ArtifactSource + ?Sized,
+{
+    let transaction = SignedTransaction::decode(raw)?;
+    let sender = transaction.sender()?;
+
+    let abis = [
+        ("ScamIco", artifacts.load("ScamIco")?.abi),
+        ("WETH", artifacts.load("MagicWeth")?.abi),
+        ("Scam", artifacts.load("Scam")?.abi),
+    ];
+    let data = &transaction.transaction.data.0;
+    let call = match transaction.transaction.to {
+        Some(_) if !data.is_empty() => {
+            let matches: Vec<_> = abis
+                .iter()
+                .filter_map(|(name, abi)| Some((*name, contract::decode_call(abi, data).ok()?)))
+                .collect();
+            matches.first().map(|(_, (function, params))| DecodedCall {
+                abis: matches.iter().map(|(name, _)| *name).collect(),
+                signature: contract::signature(function),
+                params: params.clone(),
+            })
+        }
+        _ => None,
+    };
+
+    Ok(DecodedTransaction {
+        transaction,
+        sender,
+        call,
+    })
+}
+
+/// Checks whether an account has sent transactions or holds ETH. Unlike
+/// `Context::is_used` it doesn't need the contracts, so that accounts can be
+/// discovered before the contracts are deployed.
+pub fn has_activity<T>(
+    web3: &Web3<T>,
     account: Address,
-) -> impl Future<Item = f64, Error = ContextError>
+) -> impl Future<Item = bool, Error = ContextError>
 where
     T: Transport,
 {
-    token
-        .call::<_, _, U256>("decimals", ())
-        .and_then(move |decimals| {
-            token
-                .call::<_, _, U256>("balanceOf", account)
-                .map(move |balance| (decimals, balance))
-        })
-        .and_then(|(decimals, balance)| Ok(u256_to_f64_amount(balance, decimals.as_u32() as _)))
-        .map_err(Into::into)
+    let eth = web3.eth();
+    eth.transaction_count(account, None)
+        .join(eth.balance(account, None))
+        .map(|(nonce, balance)| !nonce.is_zero() || !balance.is_zero())
+        .map_err(ContextError::from)
+}
+
+/// Checks code retrieved with `eth_getCode` against the artifacts a contract
+/// is expected to be deployed from. Code matching any of the artifacts is
+/// fine, otherwise it is a mismatch unless some of the artifacts couldn't be
+/// compared against, in which case the code is unverified.
+fn check_code(
+    contract: &'static str,
+    address: Address,
+    artifacts: Vec<(&'static str, Artifact)>,
+    code: &[u8],
+) -> Option<CodeMismatch> {
+    let results: Vec<_> = artifacts
+        .iter()
+        .map(|(_, artifact)| artifact.matches_code(code))
+        .collect();
+    if results
+        .iter()
+        .any(|result| result.as_ref().ok() == Some(&true))
+    {
+        return None;
+    }
+
+    Some(CodeMismatch {
+        contract,
+        address,
+        expected: artifacts.into_iter().map(|(name, _)| name).collect(),
+        unverified: results.iter().any(Result::is_err),
+    })
 }
 
-fn u256_to_f64_amount(a: U256, decimals: i32) -> f64 {
-    let div = U256::from(10).pow(decimals.into());
-    let (q, r) = a.div_mod(div);
+/// Sums up the WETH transfers to the ICO by sender, ordered from largest to
+/// smallest contributor. Totals saturate instead of overflowing, since anyone
+/// can mint arbitrary amounts of MagicWeth to contribute.
+fn contributors<I>(transfers: I) -> Vec<Contributor>
+where
+    I: IntoIterator<Item = Transfer>,
+{
+    let mut totals = HashMap::new();
+    for transfer in transfers {
+        let total = totals.entry(transfer.from).or_insert_with(U256::zero);
+        *total = total.saturating_add(transfer.value);
+    }
 
-    let whole = q.low_u32() as f64;
-    let fraction = (r.low_u64() as f64) * (10.0f64).powi(-decimals);
+    let mut contributors: Vec<_> = totals
+        .into_iter()
+        .map(|(address, total)| Contributor {
+            address,
+            contribution: Amount::new(total, 18),
+        })
+        .collect();
+    contributors.sort_by(|a, b| {
+        b.contribution
+            .raw()
+            .cmp(&a.contribution.raw())
+            .then(a.address.cmp(&b.address))
+    });
+    contributors
+}
 
-    (whole + fraction)
+/// Simulates a transaction with `eth_call` against the pending block so that
+/// transactions that would revert are aborted before they are sent.
+fn preflight<T>(
+    tx: ContractTransactionBuilder<T>,
+    account: &Account,
+) -> impl Future<Item = (), Error = ContextError>
+where
+    T: Transport,
+{
+    tx.from(account.address())
+        .block(Some(BlockNumber::Pending))
+        .call::<U256>()
+        .map(|_| ())
+        .map_err(ContextError::from)
 }
 
-fn f64_amount_to_u256(a: f64, decimals: i32) -> U256 {
-    let (t, f) = (a.trunc() as u64, a.fract());
-    let whole = U256::from(t) * U256::from(10).pow(decimals.into());
-    let fraction = U256::from((f * (10.0f64).powi(decimals)) as u64);
+/// Sends a contract transaction from the specified account and waits for it to
+/// be confirmed. Accounts with a private key sign the transaction offline and
+/// send it as a raw transaction, otherwise the node is expected to sign it.
+/// Watch-only accounts are refused and transactions that revert are replayed to
+/// recover the revert reason.
+fn send_from<T>(
+    tx: ContractTransactionBuilder<T>,
+    account: &Account,
+    chain_id: u64,
+    confirmations: usize,
+    timeout: Duration,
+    gas: GasOptions,
+) -> impl Future<Item = TransactionOutcome, Error = ContextError>
+where
+    T: Transport,
+{
+    use Either::*;
+
+    if account.is_watch_only() {
+        return A(future::err(ContextError::WatchOnly(account.address())));
+    }
+
+    let eth = tx.eth();
+    let tx = gas.apply(tx);
+    B(match account.secret() {
+        Some(secret) => tx.sign(Some(secret.clone()), Some(chain_id)),
+        None => tx.from(account.address()),
+    }
+    .send_pending()
+    .map_err(ContextError::from)
+    .and_then(move |tx| {
+        tx.confirmations(confirmations)
+            .timeout(timeout)
+            .wait()
+            .map_err(ContextError::from)
+    })
+    .and_then(move |outcome| {
+        if outcome.success {
+            return A(future::ok(outcome));
+        }
+        B(revert::replay(eth, outcome.hash, outcome.block_number)
+            .map_err(ContextError::from)
+            .and_then(|reason| Err(ContextError::Reverted { reason })))
+    }))
+}
 
-    (whole + fraction)
+fn erc20_balance<D, B>(decimals: D, balance: B) -> impl Future<Item = Amount, Error = ContextError>
+where
+    D: Future<Item = U256, Error = Web3ContractError>,
+    B: Future<Item = U256, Error = Web3ContractError>,
+{
+    decimals
+        .join(balance)
+        .map_err(ContextError::from)
+        .and_then(|(decimals, balance)| {
+            let decimals = Amount::checked_decimals(decimals)?;
+            Ok(Amount::new(balance, decimals))
+        })
 }
 
 #[derive(Debug, Error)]
@@ -254,19 +745,473 @@ pub enum ContextError {
     #[error("failed to load artifact: {0}")]
     Artifact(#[from] ArtifactError),
 
+    #[error("failed to resolve contract network: {0}")]
+    Network(#[from] NetworkError),
+
+    #[error("failed to deploy contract: {0}")]
+    Deploy(#[from] DeployError),
+
+    #[error("failed to confirm transaction: {0}")]
+    Wait(#[from] WaitError),
+
+    #[error("failed to sign transaction with the account's private key")]
+    Signing,
+
     #[error("web3 error: {0}")]
-    Web3(#[from] Web3Error),
+    Web3(#[source] Web3Error),
 
     #[error("web3 contract error: {0}")]
-    Web3Contract(#[from] Web3ContractError),
+    Web3Contract(#[source] Web3ContractError),
+
+    #[error("execution reverted: {reason}")]
+    Reverted { reason: RevertReason },
+
+    #[error("ICO is closed")]
+    IcoClosed,
+
+    #[error("amount being funded {amount} is larger than the remaining {remaining} WETH")]
+    AmountTooLarge { amount: Amount, remaining: Amount },
+
+    #[error("not enough WETH to fund {amount}, balance is only {balance}")]
+    InsufficientBalance { balance: Amount, amount: Amount },
+
+    #[error("tokens not ready to be claimed yet")]
+    ClaimNotReady,
+
+    #[error("nothing to claim")]
+    NothingToClaim,
 
     #[error("unknown ICO state {0:?}")]
     UnknownIcoState(U256),
+
+    #[error("invalid amount: {0}")]
+    Amount(#[from] AmountError),
+
+    #[error("account {0:?} is watch-only and cannot send transactions")]
+    WatchOnly(Address),
+
+    #[error("no accounts available, specify a mnemonic, key files or private keys")]
+    NoAccounts,
+
+    #[error("deployer {0:?} is not one of the wallet's accounts")]
+    UnknownDeployer(Address),
+
+    #[error("failed to load wallet: {0}")]
+    Wallet(#[source] Box<WalletError>),
+
+    #[error("key file passwords do not match")]
+    PasswordMismatch,
+
+    #[error("terminal error: {0}")]
+    Terminal(#[from] IoError),
+
+    #[error("failed to decode raw transaction: {0}")]
+    Decode(#[from] DecodeError),
+}
+
+impl From<Web3Error> for ContextError {
+    fn from(err: Web3Error) -> Self {
+        match RevertReason::from_web3_error(&err) {
+            Some(reason) => ContextError::Reverted { reason },
+            None => ContextError::Web3(err),
+        }
+    }
+}
+
+impl From<SendError> for ContextError {
+    fn from(err: SendError) -> Self {
+        match err {
+            SendError::Web3(err) => err.into(),
+            SendError::Signing => ContextError::Signing,
+        }
+    }
+}
+
+impl From<WalletError> for ContextError {
+    fn from(err: WalletError) -> Self {
+        match err {
+            // don't wrap errors that originated from querying the context
+            WalletError::Context(err) => err,
+            err => ContextError::Wallet(Box::new(err)),
+        }
+    }
+}
+
+impl From<Web3ContractError> for ContextError {
+    fn from(err: Web3ContractError) -> Self {
+        match err {
+            Web3ContractError::Api(err) => err.into(),
+            err => ContextError::Web3Contract(err),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum State {
-    Funding(f64),
+    Funding(Amount),
     Closed,
     Finished,
 }
+
+/// A contract whose code does not match the artifacts it is expected to be
+/// deployed from.
+#[derive(Clone, Debug)]
+pub struct CodeMismatch {
+    pub contract: &'static str,
+    pub address: Address,
+    pub expected: Vec<&'static str>,
+    /// Whether the code could not be compared against all of the artifacts,
+    /// for example because they have no deployed bytecode.
+    pub unverified: bool,
+}
+
+impl Display for CodeMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.unverified {
+            write!(
+                f,
+                "could not verify that {} contract at {:?} has {} code, \
+                 the artifacts have no usable deployed bytecode",
+                self.contract,
+                self.address,
+                self.expected.join(" or "),
+            )
+        } else {
+            write!(
+                f,
+                "{} contract at {:?} does not have {} code",
+                self.contract,
+                self.address,
+                self.expected.join(" or "),
+            )
+        }
+    }
+}
+
+/// The outcome of funding the ICO, along with the access list estimate when
+/// the funding was sent with a generated access list.
+pub struct FundOutcome {
+    pub transaction: TransactionOutcome,
+    pub access_list: Option<AccessListEstimate>,
+}
+
+impl Display for FundOutcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.transaction)?;
+        if let Some(estimate) = &self.access_list {
+            write!(
+                f,
+                ", the access list of {} addresses was estimated to use {} gas instead of {}",
+                estimate.access_list.len(),
+                estimate.gas_used,
+                estimate.gas_without,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DecodedTransaction {
+    pub transaction: SignedTransaction,
+    pub sender: Address,
+    pub call: Option<DecodedCall>,
+}
+
+impl Display for DecodedTransaction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let tx = &self.transaction.transaction;
+        let kind = match tx.transaction_type() {
+            0 => "legacy",
+            1 => "EIP-2930",
+            _ => "EIP-1559",
+        };
+
+        writeln!(f, "Hash:      {:?}", self.transaction.hash)?;
+        writeln!(f, "Type:      {}", kind)?;
+        match self.transaction.chain_id {
+            Some(chain_id) => writeln!(f, "Chain ID:  {}", chain_id)?,
+            None => writeln!(
+                f,
+                "Chain ID:  none (WARNING: no replay protection, valid on any chain)"
+            )?,
+        }
+        writeln!(f, "From:      {:?}", self.sender)?;
+        match tx.to {
+            Some(to) => writeln!(f, "To:        {:?}", to)?,
+            None => writeln!(f, "To:        contract creation")?,
+        }
+        writeln!(f, "Nonce:     {}", tx.nonce)?;
+        writeln!(f, "Value:     {} ETH", Amount::new(tx.value, 18))?;
+        writeln!(f, "Gas:       {}", tx.gas)?;
+        match &tx.gas_pricing {
+            GasPricing::Legacy(gas_price) => writeln!(f, "Gas price: {} wei", gas_price)?,
+            GasPricing::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                writeln!(f, "Max fee:   {} wei", max_fee_per_gas)?;
+                writeln!(f, "Priority:  {} wei", max_priority_fee_per_gas)?;
+            }
+        }
+        if let Some(access_list) = &tx.access_list {
+            for item in access_list {
+                writeln!(
+                    f,
+                    "Access:    {:?} ({} storage keys)",
+                    item.address,
+                    item.storage_keys.len()
+                )?;
+            }
+        }
+        match &self.call {
+            Some(call) => write!(f, "Call:      {}", call),
+            None if tx.data.0.is_empty() => write!(f, "Call:      none"),
+            None => write!(f, "Call:      unknown ({} bytes of data)", tx.data.0.len()),
+        }
+    }
+}
+
+/// A contract function call decoded from transaction calldata.
+pub struct DecodedCall {
+    /// The contract ABIs that have a function matching the call.
+    pub abis: Vec<&'static str>,
+    pub signature: String,
+    pub params: Vec<Token>,
+}
+
+impl Display for DecodedCall {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|param| match param {
+                Token::Address(address) => format!("{:?}", address),
+                Token::Uint(value) | Token::Int(value) => value.to_string(),
+                param => param.to_string(),
+            })
+            .collect();
+
+        write!(
+            f,
+            "{} with ({}) (matches {} ABI)",
+            self.signature,
+            params.join(", "),
+            self.abis.join(", ")
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Contributor {
+    pub address: Address,
+    pub contribution: Amount,
+}
+
+impl Contributor {
+    /// The share of the ICO target contributed, as a percentage with two
+    /// decimals.
+    pub fn share(&self) -> Amount {
+        // dividing the target first keeps the computation from overflowing for
+        // arbitrarily large contributions, it is exact since the target is a
+        // whole number of WETH
+        let target = U256::from(ICO_TARGET) * U256::exp10(18);
+        Amount::new(self.contribution.raw() / (target / 10_000), 2)
+    }
+
+    /// The amount of SCM the contributor can claim once the ICO finishes.
+    pub fn projected_scm(&self) -> Amount {
+        Amount::new(self.contribution.raw().saturating_mul(ICO_RATE.into()), 18)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifacts::EmbeddedArtifacts;
+    use crate::contract::RawTransaction;
+    use ethsign::SecretKey;
+    use web3::types::Bytes;
+
+    fn transfer(from: u8, value: U256) -> Transfer {
+        Transfer {
+            from: Address::from_slice(&[from; 20]),
+            to: Address::from_slice(&[0xff; 20]),
+            value,
+        }
+    }
+
+    fn weth(amount: u64) -> U256 {
+        U256::from(amount) * U256::exp10(18)
+    }
+
+    #[test]
+    fn aggregate_contributions() {
+        let contributors = contributors(vec![
+            transfer(1, weth(5)),
+            transfer(2, weth(20)),
+            transfer(1, weth(10)),
+            transfer(3, weth(15)),
+        ]);
+
+        let summary: Vec<_> = contributors
+            .iter()
+            .map(|contributor| (contributor.address, contributor.contribution.raw()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Address::from_slice(&[2; 20]), weth(20)),
+                (Address::from_slice(&[1; 20]), weth(15)),
+                (Address::from_slice(&[3; 20]), weth(15)),
+            ]
+        );
+        assert!(super::contributors(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn contribution_share() {
+        let shares: Vec<_> = contributors(vec![
+            transfer(1, weth(25)),
+            transfer(2, U256::exp10(16)),
+            transfer(3, U256::one()),
+        ])
+        .iter()
+        .map(|contributor| {
+            (
+                contributor.share().to_string(),
+                contributor.projected_scm().to_string(),
+            )
+        })
+        .collect();
+
+        assert_eq!(
+            shares,
+            vec![
+                ("25".to_string(), "250".to_string()),
+                ("0.01".to_string(), "0.1".to_string()),
+                ("0".to_string(), "0.00000000000000001".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn contributions_saturate() {
+        let contributors = contributors(vec![
+            transfer(1, U256::max_value()),
+            transfer(1, U256::max_value()),
+            transfer(2, weth(1)),
+        ]);
+        assert_eq!(contributors[0].contribution.raw(), U256::max_value());
+        assert_eq!(contributors[1].contribution.raw(), weth(1));
+
+        let whale = &contributors[0];
+        assert_eq!(
+            whale.share().raw(),
+            U256::max_value() / (weth(ICO_TARGET) / 10_000)
+        );
+        assert_eq!(whale.projected_scm().raw(), U256::max_value());
+    }
+
+    #[test]
+    fn check_contract_code() {
+        let artifact = |deployed: &str| -> Artifact {
+            serde_json::from_value(serde_json::json!({
+                "abi": [],
+                "deployedBytecode": deployed,
+            }))
+            .unwrap()
+        };
+        let address = Address::from_slice(&[1; 20]);
+        let check = |artifacts, code: &[u8]| check_code("WETH", address, artifacts, code);
+
+        assert!(check(vec![("WETH9", artifact("0x6001"))], &[0x60, 0x01]).is_none());
+        assert!(check(
+            vec![
+                ("WETH9", artifact("0x6002")),
+                ("MagicWeth", artifact("0x6001"))
+            ],
+            &[0x60, 0x01]
+        )
+        .is_none());
+        assert!(check(
+            vec![("WETH9", artifact("")), ("MagicWeth", artifact("0x6001"))],
+            &[0x60, 0x01]
+        )
+        .is_none());
+
+        let mismatch = check(
+            vec![
+                ("WETH9", artifact("0x6002")),
+                ("MagicWeth", artifact("0x6003")),
+            ],
+            &[0x60, 0x01],
+        )
+        .unwrap();
+        assert!(!mismatch.unverified);
+        assert_eq!(mismatch.expected, vec!["WETH9", "MagicWeth"]);
+        assert_eq!(
+            mismatch.to_string(),
+            "WETH contract at 0x0101010101010101010101010101010101010101 does not have \
+             WETH9 or MagicWeth code"
+        );
+
+        // code that can't be compared against all artifacts is not reported
+        // as a mismatch, but it is not verified either
+        for artifacts in vec![
+            vec![("WETH9", artifact(""))],
+            vec![("WETH9", artifact("")), ("MagicWeth", artifact("0x6003"))],
+        ] {
+            let mismatch = check(artifacts, &[0x60, 0x01]).unwrap();
+            assert!(mismatch.unverified);
+            assert!(mismatch.to_string().starts_with("could not verify"));
+        }
+    }
+
+    #[test]
+    fn decode_transaction_offline() {
+        let abi = |name| EmbeddedArtifacts.load(name).unwrap().abi;
+        let secret = SecretKey::from_raw(&[1; 32]).unwrap();
+        let sender: Address = secret.public().address().into();
+        let decode = |to: Option<Address>, data: Vec<u8>| {
+            let raw = RawTransaction {
+                nonce: 7.into(),
+                to,
+                value: 0.into(),
+                gas_pricing: GasPricing::Legacy(1_000_000_000.into()),
+                gas: 100_000.into(),
+                data: Bytes(data),
+                access_list: None,
+            }
+            .sign(secret.clone(), Some(1))
+            .unwrap();
+            decode_transaction(&raw.0, &EmbeddedArtifacts).unwrap()
+        };
+        let to = Some(Address::from_slice(&[2; 20]));
+
+        let fund = abi("ScamIco")
+            .function("fund")
+            .unwrap()
+            .encode_input(&[Token::Uint(weth(1))])
+            .unwrap();
+        let decoded = decode(to, fund);
+        assert_eq!(decoded.sender, sender);
+        assert_eq!(decoded.transaction.chain_id, Some(1));
+        let call = decoded.call.unwrap();
+        assert_eq!(call.abis, vec!["ScamIco"]);
+        assert_eq!(call.signature, "fund(uint256)");
+        assert_eq!(call.params, vec![Token::Uint(weth(1))]);
+
+        let transfer = abi("Scam")
+            .function("transfer")
+            .unwrap()
+            .encode_input(&[Token::Address(sender), Token::Uint(weth(2))])
+            .unwrap();
+        let call = decode(to, transfer).call.unwrap();
+        assert_eq!(call.abis, vec!["WETH", "Scam"]);
+        assert_eq!(call.signature, "transfer(address,uint256)");
+
+        assert!(decode(to, Vec::new()).call.is_none());
+        assert!(decode(to, vec![0xde, 0xad, 0xbe, 0xef]).call.is_none());
+        assert!(decode(None, vec![0x60, 0x80]).call.is_none());
+    }
+}
@@ -1,17 +1,27 @@
-#![allow(dead_code)]
-
-use crate::truffle::Artifact;
-use ethabi::{Contract as AbiContract, Function, Result as AbiResult};
+use crate::transaction::{PendingTransaction, WaitError};
+use crate::truffle::{Artifact, ArtifactError};
+use ethabi::{
+    Contract as AbiContract, Event, Function, Log as AbiLog, RawLog, RawTopicFilter,
+    Result as AbiResult, Token, Topic,
+};
 use ethsign::{SecretKey, Signature};
-use rlp::RlpStream;
-use web3::api::Eth;
+use rlp::{DecoderError, Rlp, RlpStream};
+use rustc_hex::{FromHex, ToHex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use thiserror::Error;
+use tokio_timer::Timer;
+use web3::api::{Eth, Namespace};
 use web3::contract::tokens::{Detokenize, Tokenize};
 use web3::contract::{Contract as Web3Contract, Error as Web3ContractError, QueryResult};
 use web3::error::Error as Web3Error;
 use web3::futures::future::{self, Either};
-use web3::futures::Future;
+use web3::futures::{stream, Future, Stream};
+use web3::helpers::{self, CallFuture};
 use web3::types::{
-    Address, BlockNumber, Bytes, CallRequest, TransactionCondition, TransactionRequest, H256, U256,
+    Address, BlockNumber, Bytes, CallRequest, Filter, FilterBuilder, Log, TransactionCondition,
+    TransactionRequest, H256, U256,
 };
 use web3::{Transport, Web3};
 
@@ -23,13 +33,93 @@ pub struct Contract<T: Transport> {
 }
 
 impl<T: Transport> Contract<T> {
+    /// Creates a contract for the artifact's deployment on the node's network.
+    /// The deployment is looked up by network ID first and then by chain ID,
+    /// since the two differ for some networks and test nodes.
     pub fn new(
         web3: Web3<T>,
         artifact: Artifact,
-    ) -> impl Future<Item = Contract<T>, Error = Web3Error> {
-        web3.net().version().and_then(move |network_id| {
-            let address = artifact.networks[&network_id].address;
-            Ok(Contract::at(web3, address, artifact))
+    ) -> impl Future<Item = Contract<T>, Error = NetworkError> {
+        web3.net()
+            .version()
+            .join(chain_id(&web3))
+            .map_err(NetworkError::from)
+            .and_then(move |(network_id, chain_id)| {
+                let address = match artifact
+                    .networks
+                    .get(&network_id)
+                    .or_else(|| artifact.networks.get(&chain_id.to_string()))
+                {
+                    Some(network) => network.address,
+                    None => {
+                        return Err(NetworkError::NotDeployed {
+                            contract: artifact.contract_name.clone(),
+                            network_id,
+                            chain_id,
+                            available: available_networks(&artifact),
+                        })
+                    }
+                };
+                Ok(Contract::at(web3, address, artifact))
+            })
+    }
+
+    /// Creates a contract for the artifact's deployment on the network with the
+    /// specified key.
+    pub fn on_network(
+        web3: Web3<T>,
+        artifact: Artifact,
+        network: &str,
+    ) -> Result<Contract<T>, NetworkError> {
+        let address = match artifact.networks.get(network) {
+            Some(network) => network.address,
+            None => {
+                return Err(NetworkError::UnknownNetwork {
+                    contract: artifact.contract_name.clone(),
+                    network: network.to_string(),
+                    available: available_networks(&artifact),
+                })
+            }
+        };
+        Ok(Contract::at(web3, address, artifact))
+    }
+
+    /// Creates a builder for a transaction deploying the artifact's contract
+    /// with the specified constructor parameters.
+    pub fn deploy<P>(
+        web3: Web3<T>,
+        artifact: Artifact,
+        params: P,
+    ) -> Result<DeployBuilder<T>, DeployError>
+    where
+        P: Tokenize,
+    {
+        let code = artifact.decode_bytecode()?;
+        let params = params.into_tokens();
+        let (inputs, data) = match artifact.abi.constructor() {
+            Some(constructor) => (
+                constructor.inputs.clone(),
+                constructor.encode_input(code, &params)?,
+            ),
+            None if params.is_empty() => (Vec::new(), code),
+            None => return Err(DeployError::UnexpectedParameters),
+        };
+
+        // represent the constructor as a function so that the transaction can
+        // be built, signed and sent like any other contract transaction
+        let constructor = Function {
+            name: "constructor".to_string(),
+            inputs,
+            outputs: Vec::new(),
+            constant: false,
+        };
+        let tx = ContractTransactionBuilder::new(web3.eth(), constructor, None, data.into());
+
+        Ok(DeployBuilder {
+            web3,
+            artifact,
+            tx,
+            confirmations: 0,
         })
     }
 
@@ -48,49 +138,391 @@ impl<T: Transport> Contract<T> {
         self.contract.address()
     }
 
-    pub fn function<S, P>(&self, name: S, params: P) -> ContractTransactionBuilder<T>
+    /// Creates a builder for a transaction calling a function. The function
+    /// can be specified by name, by full signature such as
+    /// `transfer(address,uint256)`, or by its hex encoded 4-byte selector such
+    /// as `0xa9059cbb`. Names of overloaded functions are ambiguous and need
+    /// to be specified by signature or selector.
+    #[allow(dead_code)]
+    pub fn try_function<S, P>(
+        &self,
+        name: S,
+        params: P,
+    ) -> Result<ContractTransactionBuilder<T>, FunctionError>
     where
         S: AsRef<str>,
         P: Tokenize,
     {
-        self.try_function(name, params).unwrap()
+        let function = self.find_function(name.as_ref())?;
+        let data = function.encode_input(&params.into_tokens())?;
+
+        Ok(ContractTransactionBuilder::new(
+            self.web3.eth(),
+            function.clone(),
+            Some(self.address()),
+            data.into(),
+        ))
+    }
+
+    /// Looks up a function by name, full signature or 4-byte selector.
+    pub fn find_function(&self, name: &str) -> Result<&Function, FunctionError> {
+        find_function(&self.abi, name)
+    }
+
+    /// Creates a builder for a transaction calling a function with parameter
+    /// tokens that were already type checked against the function ABI, as is
+    /// done by the generated contract bindings.
+    pub fn typed_function(
+        &self,
+        function: Function,
+        params: Vec<Token>,
+    ) -> ContractTransactionBuilder<T> {
+        let mut data = function.short_signature().to_vec();
+        data.extend(ethabi::encode(&params));
+
+        ContractTransactionBuilder::new(
+            self.web3.eth(),
+            function,
+            Some(self.address()),
+            data.into(),
+        )
     }
 
-    pub fn try_function<S, P>(&self, name: S, params: P) -> AbiResult<ContractTransactionBuilder<T>>
+    pub fn events<S>(&self, name: S) -> AbiResult<EventQueryBuilder<T>>
     where
         S: AsRef<str>,
-        P: Tokenize,
     {
-        let function = self.abi.function(name.as_ref())?;
-        let data = function.encode_input(&params.into_tokens())?;
+        let event = self.abi.event(name.as_ref())?;
 
-        Ok(ContractTransactionBuilder::new(
+        Ok(EventQueryBuilder::new(
             self.web3.eth(),
-            function.clone(),
+            event.clone(),
             self.address(),
-            data.into(),
         ))
     }
 
-    pub fn call<S, P, R>(
+    #[allow(dead_code)]
+    pub fn subscribe<S>(
         &self,
         name: S,
-        params: P,
-    ) -> impl Future<Item = R, Error = Web3ContractError>
+    ) -> impl Future<Item = EventSubscription<T>, Error = Web3ContractError>
     where
         S: AsRef<str>,
-        P: Tokenize,
-        R: Detokenize,
     {
-        self.function(name, params).call()
+        match self.events(name) {
+            Ok(events) => Either::A(events.subscribe()),
+            Err(err) => Either::B(future::err(err.into())),
+        }
+    }
+}
+
+pub struct EventQueryBuilder<T: Transport> {
+    eth: Eth<T>,
+    event: Event,
+    address: Address,
+    topics: RawTopicFilter,
+    from_block: BlockNumber,
+    to_block: BlockNumber,
+}
+
+impl<T: Transport> EventQueryBuilder<T> {
+    fn new(eth: Eth<T>, event: Event, address: Address) -> EventQueryBuilder<T> {
+        EventQueryBuilder {
+            eth,
+            event,
+            address,
+            topics: RawTopicFilter::default(),
+            from_block: BlockNumber::Earliest,
+            to_block: BlockNumber::Latest,
+        }
+    }
+
+    /// Filters on the first indexed event parameter.
+    pub fn topic0(mut self, topic: Topic<Token>) -> EventQueryBuilder<T> {
+        self.topics.topic0 = topic;
+        self
+    }
+
+    /// Filters on the second indexed event parameter.
+    pub fn topic1(mut self, topic: Topic<Token>) -> EventQueryBuilder<T> {
+        self.topics.topic1 = topic;
+        self
+    }
+
+    /// Filters on the third indexed event parameter.
+    #[allow(dead_code)]
+    pub fn topic2(mut self, topic: Topic<Token>) -> EventQueryBuilder<T> {
+        self.topics.topic2 = topic;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn from_block(mut self, block: BlockNumber) -> EventQueryBuilder<T> {
+        self.from_block = block;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn to_block(mut self, block: BlockNumber) -> EventQueryBuilder<T> {
+        self.to_block = block;
+        self
+    }
+
+    /// Queries the logs matching the filter with `eth_getLogs` and decodes them
+    /// with the event ABI.
+    pub fn query(self) -> impl Future<Item = Vec<EventLog<AbiLog>>, Error = Web3ContractError> {
+        use Either::*;
+
+        let topics = match self.event.create_filter(self.topics) {
+            Ok(topics) => topics,
+            Err(err) => return A(future::err(err.into())),
+        };
+        let filter = FilterBuilder::default()
+            .address(vec![self.address])
+            .topic_filter(topics)
+            .from_block(self.from_block)
+            .to_block(self.to_block)
+            .build();
+
+        let event = self.event;
+        B(self
+            .eth
+            .logs(filter)
+            .map_err(Web3ContractError::from)
+            .and_then(move |logs| {
+                logs.into_iter()
+                    .map(|log| EventLog::decode(&event, log))
+                    .collect()
+            }))
     }
 
-    pub fn send<S, P>(&self, name: S, params: P) -> impl Future<Item = H256, Error = Web3Error>
+    /// Queries the logs matching the filter and converts the decoded event
+    /// parameters into a typed value.
+    pub fn query_typed<E>(self) -> impl Future<Item = Vec<EventLog<E>>, Error = Web3ContractError>
     where
-        S: AsRef<str>,
-        P: Tokenize,
+        E: Detokenize,
+    {
+        self.query()
+            .and_then(|logs| logs.into_iter().map(EventLog::detokenize).collect())
+    }
+
+    /// Subscribes to new logs matching the filter. The block range of the
+    /// builder is ignored, only logs from blocks mined after the subscription
+    /// was installed are reported.
+    pub fn subscribe(self) -> impl Future<Item = EventSubscription<T>, Error = Web3ContractError> {
+        use Either::*;
+
+        let topics = match self.event.create_filter(self.topics) {
+            Ok(topics) => topics,
+            Err(err) => return A(future::err(err.into())),
+        };
+        let filter = LogFilter {
+            address: self.address,
+            topics: [
+                topic(topics.topic0),
+                topic(topics.topic1),
+                topic(topics.topic2),
+                topic(topics.topic3),
+            ],
+        };
+
+        let EventQueryBuilder { eth, event, .. } = self;
+        let install = CallFuture::new(eth.transport().execute(
+            "eth_newFilter",
+            vec![helpers::serialize(
+                &filter.build(BlockNumber::Latest, BlockNumber::Latest),
+            )],
+        ))
+        // not all nodes support filters, in which case logs are polled instead
+        .then(|filter_id: Result<U256, Web3Error>| Ok(filter_id.ok()));
+
+        B(eth
+            .block_number()
+            .join(install)
+            .map(move |(current_block, filter_id)| EventSubscription {
+                eth,
+                event,
+                filter,
+                filter_id,
+                next_block: current_block.as_u64() + 1,
+            })
+            .map_err(Web3ContractError::from))
+    }
+}
+
+fn topic(topic: Topic<H256>) -> Option<Vec<H256>> {
+    match topic {
+        Topic::Any => None,
+        Topic::OneOf(topics) => Some(topics),
+        Topic::This(topic) => Some(vec![topic]),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct LogFilter {
+    address: Address,
+    topics: [Option<Vec<H256>>; 4],
+}
+
+impl LogFilter {
+    fn build(&self, from_block: BlockNumber, to_block: BlockNumber) -> Filter {
+        let [topic0, topic1, topic2, topic3] = self.topics.clone();
+        FilterBuilder::default()
+            .address(vec![self.address])
+            .topics(topic0, topic1, topic2, topic3)
+            .from_block(from_block)
+            .to_block(to_block)
+            .build()
+    }
+}
+
+/// A subscription to new event logs. New logs are retrieved with
+/// `eth_getFilterChanges` from a filter installed on the node, or with
+/// incremental `eth_getLogs` queries if the node doesn't support filters or
+/// has dropped the filter.
+pub struct EventSubscription<T: Transport> {
+    eth: Eth<T>,
+    event: Event,
+    filter: LogFilter,
+    filter_id: Option<U256>,
+    next_block: u64,
+}
+
+impl<T: Transport> EventSubscription<T> {
+    /// Retrieves the logs since the last poll and returns them along with the
+    /// subscription to use for the next poll.
+    pub fn changes(
+        self,
+    ) -> impl Future<Item = (Vec<EventLog<AbiLog>>, EventSubscription<T>), Error = Web3ContractError>
+    {
+        use Either::*;
+
+        let logs = match self.filter_id {
+            Some(filter_id) => A(CallFuture::new(
+                self.eth
+                    .transport()
+                    .execute("eth_getFilterChanges", vec![helpers::serialize(&filter_id)]),
+            )
+            .then(move |logs: Result<Vec<Log>, Web3Error>| match logs {
+                Ok(logs) => A(future::ok((logs, self))),
+                Err(_) => B(EventSubscription {
+                    filter_id: None,
+                    ..self
+                }
+                .poll_logs()),
+            })),
+            None => B(self.poll_logs()),
+        };
+
+        logs.map_err(Web3ContractError::from)
+            .and_then(|(logs, subscription)| subscription.decode(logs))
+    }
+
+    /// Turns the subscription into a stream of logs that polls for changes at
+    /// the specified interval.
+    pub fn stream(
+        self,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = EventLog<AbiLog>, Error = Web3ContractError> {
+        let timer = Timer::default();
+
+        stream::unfold(self, move |subscription| {
+            Some(
+                timer
+                    .sleep(poll_interval)
+                    .map_err(|err| Web3ContractError::from(Web3Error::Transport(err.to_string())))
+                    .and_then(move |_| subscription.changes()),
+            )
+        })
+        .map(stream::iter_ok::<_, Web3ContractError>)
+        .flatten()
+    }
+
+    fn poll_logs(self) -> impl Future<Item = (Vec<Log>, EventSubscription<T>), Error = Web3Error> {
+        use Either::*;
+
+        let eth = self.eth.clone();
+        eth.block_number().and_then(move |current_block| {
+            let current_block = current_block.as_u64();
+            if current_block < self.next_block {
+                return A(future::ok((Vec::new(), self)));
+            }
+
+            let filter = self.filter.build(
+                BlockNumber::Number(self.next_block.into()),
+                BlockNumber::Number(current_block.into()),
+            );
+            B(eth.logs(filter).map(move |logs| {
+                let subscription = EventSubscription {
+                    next_block: current_block + 1,
+                    ..self
+                };
+                (logs, subscription)
+            }))
+        })
+    }
+
+    fn decode(
+        mut self,
+        logs: Vec<Log>,
+    ) -> Result<(Vec<EventLog<AbiLog>>, EventSubscription<T>), Web3ContractError> {
+        let logs = logs
+            .into_iter()
+            .filter(|log| log.removed != Some(true))
+            .map(|log| EventLog::decode(&self.event, log))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // remember the last block logs were seen in so that polling can resume
+        // from there should the filter go away
+        if let Some(block) = logs.iter().filter_map(|log| log.block_number).max() {
+            self.next_block = self.next_block.max(block + 1);
+        }
+
+        Ok((logs, self))
+    }
+}
+
+/// A decoded event log along with its position in the chain.
+#[derive(Clone, Debug)]
+pub struct EventLog<E> {
+    pub data: E,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<H256>,
+    pub log_index: Option<U256>,
+}
+
+impl EventLog<AbiLog> {
+    pub fn decode(event: &Event, log: Log) -> Result<EventLog<AbiLog>, Web3ContractError> {
+        let data = event.parse_log(RawLog {
+            topics: log.topics,
+            data: log.data.0,
+        })?;
+
+        Ok(EventLog {
+            data,
+            block_number: log.block_number.map(|block| block.as_u64()),
+            transaction_hash: log.transaction_hash,
+            log_index: log.log_index,
+        })
+    }
+
+    pub fn detokenize<E>(self) -> Result<EventLog<E>, Web3ContractError>
+    where
+        E: Detokenize,
     {
-        self.function(name, params).send()
+        let tokens = self
+            .data
+            .params
+            .into_iter()
+            .map(|param| param.value)
+            .collect();
+
+        Ok(EventLog {
+            data: E::from_tokens(tokens)?,
+            block_number: self.block_number,
+            transaction_hash: self.transaction_hash,
+            log_index: self.log_index,
+        })
     }
 }
 
@@ -101,13 +533,18 @@ pub struct ContractTransactionBuilder<T: Transport> {
     block: Option<BlockNumber>,
     secret: Option<SecretKey>,
     chain_id: Option<u64>,
+    max_fee_per_gas: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    eip1559: bool,
+    access_list: Option<Vec<AccessListItem>>,
+    create_access_list: bool,
 }
 
 impl<T: Transport> ContractTransactionBuilder<T> {
     fn new(
         eth: Eth<T>,
         function: Function,
-        contract: Address,
+        contract: Option<Address>,
         data: Bytes,
     ) -> ContractTransactionBuilder<T> {
         ContractTransactionBuilder {
@@ -115,7 +552,7 @@ impl<T: Transport> ContractTransactionBuilder<T> {
             function,
             tx: TransactionRequest {
                 from: Address::zero(),
-                to: Some(contract),
+                to: contract,
                 gas: None,
                 gas_price: None,
                 value: None,
@@ -126,14 +563,24 @@ impl<T: Transport> ContractTransactionBuilder<T> {
             block: None,
             secret: None,
             chain_id: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            eip1559: false,
+            access_list: None,
+            create_access_list: false,
         }
     }
 
+    pub fn eth(&self) -> Eth<T> {
+        self.eth.clone()
+    }
+
     pub fn from(mut self, from: Address) -> ContractTransactionBuilder<T> {
         self.tx.from = from;
         self
     }
 
+    #[allow(dead_code)]
     pub fn gas(mut self, gas: Option<U256>) -> ContractTransactionBuilder<T> {
         self.tx.gas = gas;
         self
@@ -144,16 +591,68 @@ impl<T: Transport> ContractTransactionBuilder<T> {
         self
     }
 
+    /// Sets the maximum fee per gas, including the base fee, of an EIP-1559
+    /// transaction. Setting either EIP-1559 fee sends an EIP-1559 transaction.
+    pub fn max_fee_per_gas(
+        mut self,
+        max_fee_per_gas: Option<U256>,
+    ) -> ContractTransactionBuilder<T> {
+        self.max_fee_per_gas = max_fee_per_gas;
+        self.eip1559 |= max_fee_per_gas.is_some();
+        self
+    }
+
+    /// Sets the maximum priority fee per gas paid to the miner of an EIP-1559
+    /// transaction.
+    pub fn max_priority_fee_per_gas(
+        mut self,
+        max_priority_fee_per_gas: Option<U256>,
+    ) -> ContractTransactionBuilder<T> {
+        self.max_priority_fee_per_gas = max_priority_fee_per_gas;
+        self.eip1559 |= max_priority_fee_per_gas.is_some();
+        self
+    }
+
+    /// Sends an EIP-1559 transaction instead of a legacy transaction. Fees
+    /// that are not set are estimated from the node's suggested priority fee
+    /// and the base fee of the latest block.
+    pub fn eip1559(mut self, eip1559: bool) -> ContractTransactionBuilder<T> {
+        self.eip1559 = eip1559;
+        self
+    }
+
+    /// Sets the EIP-2930 access list of addresses and storage slots that the
+    /// transaction accesses. Legacy priced transactions with an access list
+    /// are sent as EIP-2930 transactions.
+    pub fn access_list(
+        mut self,
+        access_list: Option<Vec<AccessListItem>>,
+    ) -> ContractTransactionBuilder<T> {
+        self.access_list = access_list;
+        self
+    }
+
+    /// Generates the access list with `eth_createAccessList` against the
+    /// pending block before sending the transaction, replacing any access list
+    /// that was set.
+    #[allow(dead_code)]
+    pub fn create_access_list(mut self, create: bool) -> ContractTransactionBuilder<T> {
+        self.create_access_list = create;
+        self
+    }
+
     pub fn value(mut self, value: Option<U256>) -> ContractTransactionBuilder<T> {
         self.tx.value = value;
         self
     }
 
+    #[allow(dead_code)]
     pub fn nonce(mut self, nonce: Option<U256>) -> ContractTransactionBuilder<T> {
         self.tx.nonce = nonce;
         self
     }
 
+    #[allow(dead_code)]
     pub fn condition(
         mut self,
         condition: Option<TransactionCondition>,
@@ -180,54 +679,197 @@ impl<T: Transport> ContractTransactionBuilder<T> {
         self
     }
 
-    fn build_raw_transaction(self) -> impl Future<Item = Bytes, Error = Web3Error> {
+    /// Builds the raw transaction, which expects the access list to already
+    /// be resolved.
+    fn build_raw_transaction(self) -> impl Future<Item = Bytes, Error = SendError> {
         use Either::*;
 
         let nonce = match &self.tx.nonce {
             Some(nonce) => A(future::ok(*nonce)),
-            None => B(self.eth.transaction_count(self.tx.from, None)),
+            // use the pending nonce so that multiple transactions can be sent
+            // back to back without waiting for them to be mined
+            None => B(self
+                .eth
+                .transaction_count(self.tx.from, Some(BlockNumber::Pending))),
         };
 
         let gas = match &self.tx.gas {
             Some(gas) => A(future::ok(*gas)),
-            None => B(self.eth.estimate_gas(
-                CallRequest {
-                    from: Some(self.tx.from),
-                    to: self.tx.to.expect("contract address not set in transaction"),
-                    gas: self.tx.gas,
-                    gas_price: self.tx.gas_price,
-                    value: self.tx.value,
-                    data: self.tx.data.clone(),
-                },
-                None,
+            // `eth_estimateGas` is called with the transaction request instead
+            // of a call request since contract creations have no `to` address
+            None => B(CallFuture::new(
+                self.eth
+                    .transport()
+                    .execute("eth_estimateGas", vec![self.simulation_request()]),
             )),
         };
 
-        let gas_price = match &self.tx.gas_price {
-            Some(gas_price) => A(future::ok(*gas_price)),
-            None => B(self.eth.gas_price()),
-        };
+        let gas_pricing = self.gas_pricing();
 
         nonce
-            .join3(gas, gas_price)
-            .and_then(move |(nonce, gas, gas_price)| {
+            .join(gas)
+            .map_err(SendError::from)
+            .join(gas_pricing)
+            .and_then(move |((nonce, gas), gas_pricing)| {
                 let tx = RawTransaction {
                     nonce,
-                    gas_price,
+                    gas_pricing,
                     gas,
                     to: self.tx.to,
                     value: self.tx.value.unwrap_or_default(),
                     data: self.tx.data.unwrap_or_default(),
+                    access_list: self.access_list,
                 };
 
                 if let Some(secret) = self.secret {
-                    Ok(tx.sign(secret, self.chain_id))
+                    tx.sign(secret, self.chain_id)
                 } else {
-                    Ok(tx.into_raw(self.chain_id))
+                    tx.into_raw(self.chain_id)
                 }
             })
     }
 
+    /// Resolves the gas price of a legacy transaction or the fees of an
+    /// EIP-1559 transaction, estimating the ones that are not set.
+    fn gas_pricing(&self) -> impl Future<Item = GasPricing, Error = SendError> {
+        use Either::*;
+
+        if !self.eip1559 {
+            let gas_price = match &self.tx.gas_price {
+                Some(gas_price) => A(future::ok(*gas_price)),
+                None => B(self.eth.gas_price()),
+            };
+            return A(gas_price.map(GasPricing::Legacy).map_err(SendError::from));
+        }
+
+        let transport = self.eth.transport();
+        let max_priority_fee_per_gas = match &self.max_priority_fee_per_gas {
+            Some(fee) => A(future::ok(*fee)),
+            None => B(CallFuture::new(
+                transport.execute("eth_maxPriorityFeePerGas", vec![]),
+            )),
+        };
+        let base_fee = match &self.max_fee_per_gas {
+            Some(_) => A(future::ok(None)),
+            None => B(CallFuture::new(transport.execute(
+                "eth_getBlockByNumber",
+                vec![
+                    helpers::serialize(&BlockNumber::Latest),
+                    helpers::serialize(&false),
+                ],
+            ))
+            .map(|block: Option<BlockFees>| block.and_then(|block| block.base_fee_per_gas))),
+        };
+
+        let max_fee_per_gas = self.max_fee_per_gas;
+        B(max_priority_fee_per_gas
+            .join(base_fee)
+            .map_err(SendError::from)
+            .and_then(move |(max_priority_fee_per_gas, base_fee)| {
+                let max_fee_per_gas = match (max_fee_per_gas, base_fee) {
+                    (Some(max_fee_per_gas), _) => max_fee_per_gas,
+                    // leave room for the base fee to double before the
+                    // transaction is mined
+                    (None, Some(base_fee)) => base_fee * U256::from(2) + max_priority_fee_per_gas,
+                    (None, None) => return Err(SendError::Eip1559Unsupported),
+                };
+
+                Ok(GasPricing::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                })
+            }))
+    }
+
+    /// Resolves the access list of the transaction, generating it with
+    /// `eth_createAccessList` if requested.
+    fn resolve_access_list(
+        &self,
+    ) -> impl Future<Item = Option<Vec<AccessListItem>>, Error = SendError> {
+        use Either::*;
+
+        if !self.create_access_list {
+            return A(future::ok(self.access_list.clone()));
+        }
+
+        B(self
+            .request_access_list()
+            .map(|result| Some(result.access_list)))
+    }
+
+    /// Generates the access list of the transaction with `eth_createAccessList`
+    /// and estimates the gas used without it, so that the gas used with and
+    /// without the access list can be compared.
+    pub fn estimate_access_list(
+        &self,
+    ) -> impl Future<Item = AccessListEstimate, Error = SendError> {
+        let gas_without = CallFuture::new(
+            self.eth
+                .transport()
+                .execute("eth_estimateGas", vec![self.simulation_request()]),
+        )
+        .map_err(SendError::from);
+
+        self.request_access_list()
+            .join(gas_without)
+            .map(|(result, gas_without)| AccessListEstimate {
+                access_list: result.access_list,
+                gas_used: result.gas_used,
+                gas_without,
+            })
+    }
+
+    fn request_access_list(&self) -> impl Future<Item = AccessListResult, Error = SendError> {
+        CallFuture::new(self.eth.transport().execute(
+            "eth_createAccessList",
+            vec![
+                self.simulation_request(),
+                helpers::serialize(&BlockNumber::Pending),
+            ],
+        ))
+        .map_err(SendError::from)
+        .and_then(|result: AccessListResult| match result.error {
+            Some(err) => Err(SendError::AccessList(err)),
+            None => Ok(result),
+        })
+    }
+
+    /// Serializes the transaction request for node signed transactions, adding
+    /// the EIP-2930 and EIP-1559 fields that web3's `TransactionRequest` does
+    /// not have.
+    fn request(&self, tx: &TransactionRequest) -> Value {
+        let mut request = helpers::serialize(tx);
+        if let Value::Object(fields) = &mut request {
+            if let Some(access_list) = &self.access_list {
+                fields.insert("type".into(), "0x1".into());
+                fields.insert("accessList".into(), helpers::serialize(access_list));
+            }
+            if self.eip1559 {
+                fields.remove("gasPrice");
+                fields.insert("type".into(), "0x2".into());
+                if let Some(fee) = &self.max_fee_per_gas {
+                    fields.insert("maxFeePerGas".into(), helpers::serialize(fee));
+                }
+                if let Some(fee) = &self.max_priority_fee_per_gas {
+                    fields.insert("maxPriorityFeePerGas".into(), helpers::serialize(fee));
+                }
+            }
+        }
+
+        request
+    }
+
+    /// Serializes the transaction request for estimating gas and generating
+    /// access lists, which is done with the transaction request instead of a
+    /// call request since contract creations have no `to` address.
+    fn simulation_request(&self) -> Value {
+        self.request(&TransactionRequest {
+            nonce: None,
+            condition: None,
+            ..self.tx.clone()
+        })
+    }
+
     pub fn call<R>(self) -> impl Future<Item = R, Error = Web3ContractError>
     where
         R: Detokenize,
@@ -249,55 +891,473 @@ impl<T: Transport> ContractTransactionBuilder<T> {
         )
     }
 
-    pub fn send(self) -> impl Future<Item = H256, Error = Web3Error> {
+    pub fn send_pending(self) -> impl Future<Item = PendingTransaction<T>, Error = SendError> {
+        let eth = self.eth.clone();
+        self.send()
+            .map(move |hash| PendingTransaction::new(eth, hash))
+    }
+
+    pub fn send(self) -> impl Future<Item = H256, Error = SendError> {
         use Either::*;
 
-        if self.secret.is_some() {
-            let eth = self.eth.clone();
-            A(self
-                .build_raw_transaction()
-                .map_err(|e| Web3Error::Transport(e.to_string()))
-                .and_then(move |tx| eth.send_raw_transaction(tx)))
-        } else {
-            B(self.eth.send_transaction(self.tx))
+        self.resolve_access_list().and_then(move |access_list| {
+            let tx = ContractTransactionBuilder {
+                access_list,
+                create_access_list: false,
+                ..self
+            };
+
+            if tx.secret.is_some() {
+                let eth = tx.eth.clone();
+                A(tx.build_raw_transaction()
+                    .and_then(move |tx| eth.send_raw_transaction(tx).map_err(SendError::from)))
+            } else {
+                B(CallFuture::new(
+                    tx.eth
+                        .transport()
+                        .execute("eth_sendTransaction", vec![tx.request(&tx.tx)]),
+                )
+                .map_err(SendError::from))
+            }
+        })
+    }
+}
+
+/// A builder for a contract creation transaction.
+pub struct DeployBuilder<T: Transport> {
+    web3: Web3<T>,
+    artifact: Artifact,
+    tx: ContractTransactionBuilder<T>,
+    confirmations: usize,
+}
+
+impl<T: Transport> DeployBuilder<T> {
+    pub fn from(mut self, from: Address) -> DeployBuilder<T> {
+        self.tx = self.tx.from(from);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn gas(mut self, gas: Option<U256>) -> DeployBuilder<T> {
+        self.tx = self.tx.gas(gas);
+        self
+    }
+
+    pub fn gas_price(mut self, gas_price: Option<U256>) -> DeployBuilder<T> {
+        self.tx = self.tx.gas_price(gas_price);
+        self
+    }
+
+    pub fn max_fee_per_gas(mut self, max_fee_per_gas: Option<U256>) -> DeployBuilder<T> {
+        self.tx = self.tx.max_fee_per_gas(max_fee_per_gas);
+        self
+    }
+
+    pub fn max_priority_fee_per_gas(
+        mut self,
+        max_priority_fee_per_gas: Option<U256>,
+    ) -> DeployBuilder<T> {
+        self.tx = self.tx.max_priority_fee_per_gas(max_priority_fee_per_gas);
+        self
+    }
+
+    pub fn eip1559(mut self, eip1559: bool) -> DeployBuilder<T> {
+        self.tx = self.tx.eip1559(eip1559);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn access_list(mut self, access_list: Option<Vec<AccessListItem>>) -> DeployBuilder<T> {
+        self.tx = self.tx.access_list(access_list);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn create_access_list(mut self, create: bool) -> DeployBuilder<T> {
+        self.tx = self.tx.create_access_list(create);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn value(mut self, value: Option<U256>) -> DeployBuilder<T> {
+        self.tx = self.tx.value(value);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn nonce(mut self, nonce: Option<U256>) -> DeployBuilder<T> {
+        self.tx = self.tx.nonce(nonce);
+        self
+    }
+
+    pub fn sign(mut self, secret: Option<SecretKey>, chain_id: Option<u64>) -> DeployBuilder<T> {
+        self.tx = self.tx.sign(secret, chain_id);
+        self
+    }
+
+    /// Sets the number of confirmations to wait for after the contract
+    /// creation transaction is mined.
+    #[allow(dead_code)]
+    pub fn confirmations(mut self, confirmations: usize) -> DeployBuilder<T> {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Sends the contract creation transaction and waits for it to be mined,
+    /// resolving to the deployed contract.
+    pub fn send(self) -> impl Future<Item = Contract<T>, Error = DeployError> {
+        let DeployBuilder {
+            web3,
+            artifact,
+            tx,
+            confirmations,
+        } = self;
+
+        tx.send_pending()
+            .map_err(DeployError::from)
+            .and_then(move |tx| {
+                tx.confirmations(confirmations)
+                    .wait()
+                    .map_err(DeployError::from)
+            })
+            .and_then(move |outcome| match outcome.contract_address {
+                Some(address) if outcome.success => Ok(Contract::at(web3, address, artifact)),
+                _ => Err(DeployError::Failed(outcome.hash)),
+            })
+    }
+}
+
+/// Looks up a function of a contract ABI by name, full signature or 4-byte
+/// selector. Whitespace in signatures is ignored.
+fn find_function<'a>(abi: &'a AbiContract, name: &str) -> Result<&'a Function, FunctionError> {
+    let candidates: Vec<_> = if let Some(selector) = parse_selector(name) {
+        abi.functions()
+            .filter(|function| function.short_signature() == selector)
+            .collect()
+    } else if name.contains('(') {
+        let name: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+        abi.functions()
+            .filter(|function| signature(function) == name)
+            .collect()
+    } else {
+        abi.functions_by_name(name)
+            .map(|functions| functions.iter().collect())
+            .unwrap_or_default()
+    };
+
+    match candidates.as_slice() {
+        [] => Err(FunctionError::NotFound(name.to_string())),
+        [function] => Ok(*function),
+        _ => Err(FunctionError::Ambiguous {
+            name: name.to_string(),
+            candidates: candidates
+                .iter()
+                .map(|function| signature(function))
+                .collect(),
+        }),
+    }
+}
+
+/// Parses a hex encoded 4-byte function selector.
+fn parse_selector(name: &str) -> Option<[u8; 4]> {
+    if !name.starts_with("0x") || name.len() != 10 {
+        return None;
+    }
+
+    let bytes: Vec<u8> = name[2..].from_hex().ok()?;
+    let mut selector = [0; 4];
+    selector.copy_from_slice(&bytes);
+    Some(selector)
+}
+
+/// Decodes transaction calldata into the function of a contract ABI it calls
+/// and its parameters.
+pub fn decode_call<'a>(
+    abi: &'a AbiContract,
+    data: &[u8],
+) -> Result<(&'a Function, Vec<Token>), FunctionError> {
+    let selector = format!("0x{}", data.get(..4).unwrap_or(data).to_hex::<String>());
+    let function = find_function(abi, &selector)?;
+    let kinds: Vec<_> = function
+        .inputs
+        .iter()
+        .map(|param| param.kind.clone())
+        .collect();
+
+    Ok((function, ethabi::decode(&kinds, &data[4..])?))
+}
+
+/// Formats the canonical signature of a function used to compute its
+/// selector, for example `transfer(address,uint256)`.
+pub fn signature(function: &Function) -> String {
+    let inputs: Vec<_> = function
+        .inputs
+        .iter()
+        .map(|param| param.kind.to_string())
+        .collect();
+    format!("{}({})", function.name, inputs.join(","))
+}
+
+#[derive(Debug, Error)]
+pub enum FunctionError {
+    #[error("function {0} not found in contract ABI")]
+    NotFound(String),
+
+    #[error("function name {name} is ambiguous, candidates are: {}", .candidates.join(", "))]
+    Ambiguous {
+        name: String,
+        candidates: Vec<String>,
+    },
+
+    #[error("failed to encode function parameters: {0}")]
+    Abi(#[from] ethabi::Error),
+}
+
+/// Retrieves the chain ID used for EIP-155 replay protection. This can differ
+/// from the network ID reported by `net_version`, notably for Ganache.
+pub fn chain_id<T>(web3: &Web3<T>) -> impl Future<Item = u64, Error = Web3Error>
+where
+    T: Transport,
+{
+    CallFuture::new(web3.transport().execute("eth_chainId", vec![])).and_then(|chain_id: U256| {
+        if chain_id > U256::from(u64::max_value()) {
+            return Err(Web3Error::InvalidResponse(format!(
+                "chain ID {} does not fit in 64 bits",
+                chain_id
+            )));
         }
+        Ok(chain_id.low_u64())
+    })
+}
+
+fn available_networks(artifact: &Artifact) -> Vec<String> {
+    let mut networks: Vec<_> = artifact.networks.keys().cloned().collect();
+    networks.sort();
+    networks
+}
+
+fn format_networks(networks: &[String]) -> String {
+    if networks.is_empty() {
+        "none".to_string()
+    } else {
+        networks.join(", ")
     }
 }
 
-struct RawTransaction {
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("web3 error: {0}")]
+    Web3(#[from] Web3Error),
+
+    #[error(
+        "contract {contract} is not deployed on network {network_id} (chain ID {chain_id}), \
+         available networks: {}",
+        format_networks(.available)
+    )]
+    NotDeployed {
+        contract: String,
+        network_id: String,
+        chain_id: u64,
+        available: Vec<String>,
+    },
+
+    #[error(
+        "contract {contract} has no deployment for network {network}, available networks: {}",
+        format_networks(.available)
+    )]
+    UnknownNetwork {
+        contract: String,
+        network: String,
+        available: Vec<String>,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum DeployError {
+    #[error("failed to load contract bytecode: {0}")]
+    Artifact(#[from] ArtifactError),
+
+    #[error("failed to encode constructor parameters: {0}")]
+    Abi(#[from] ethabi::Error),
+
+    #[error("contract has no constructor but parameters were specified")]
+    UnexpectedParameters,
+
+    #[error("failed to send contract creation transaction: {0}")]
+    Send(#[from] SendError),
+
+    #[error("failed to confirm contract creation transaction: {0}")]
+    Wait(#[from] WaitError),
+
+    #[error("contract creation transaction {0:?} failed")]
+    Failed(H256),
+}
+
+#[derive(Debug, Error)]
+pub enum SendError {
+    #[error("web3 error: {0}")]
+    Web3(#[from] Web3Error),
+
+    #[error("failed to sign transaction with the account's private key")]
+    Signing,
+
+    #[error("typed transactions need a chain ID to be signed")]
+    MissingChainId,
+
+    #[error("failed to create access list: {0}")]
+    AccessList(String),
+
+    #[error("node does not support EIP-1559 transactions, the latest block has no base fee")]
+    Eip1559Unsupported,
+}
+
+/// The part of a block needed to estimate EIP-1559 fees, which is missing
+/// from web3's `Block`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockFees {
+    #[serde(default)]
+    base_fee_per_gas: Option<U256>,
+}
+
+/// An address and the storage slots of it that a transaction accesses.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+/// The result of `eth_createAccessList`, which includes the error the
+/// transaction would fail with instead of returning an RPC error.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessListResult {
+    access_list: Vec<AccessListItem>,
+    gas_used: U256,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A generated access list along with the gas the transaction uses with it,
+/// as reported by `eth_createAccessList`, and without it, as estimated by
+/// `eth_estimateGas`.
+#[derive(Clone, Debug)]
+pub struct AccessListEstimate {
+    pub access_list: Vec<AccessListItem>,
+    pub gas_used: U256,
+    pub gas_without: U256,
+}
+
+/// The type reported for legacy transactions, which predate EIP-2718.
+const LEGACY_TRANSACTION_TYPE: u8 = 0;
+
+/// The EIP-2718 type of EIP-2930 transactions.
+const EIP2930_TRANSACTION_TYPE: u8 = 1;
+
+/// The EIP-2718 type of EIP-1559 transactions.
+const EIP1559_TRANSACTION_TYPE: u8 = 2;
+
+/// How a transaction pays for gas, which determines its type.
+#[derive(Clone, Debug)]
+pub enum GasPricing {
+    /// A legacy transaction with a fixed gas price.
+    Legacy(U256),
+    /// An EIP-1559 transaction that pays the block's base fee plus a priority
+    /// fee, up to a maximum fee.
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+pub struct RawTransaction {
     pub nonce: U256,
     pub to: Option<Address>,
     pub value: U256,
-    pub gas_price: U256,
+    pub gas_pricing: GasPricing,
     pub gas: U256,
     pub data: Bytes,
+    pub access_list: Option<Vec<AccessListItem>>,
 }
 
 impl RawTransaction {
-    pub fn sign(&self, key: SecretKey, chain_id: Option<u64>) -> Bytes {
-        let mut rlp = RlpStream::new();
-        self.rlp_append_unsigned(&mut rlp, chain_id);
-        let hash = tiny_keccak::keccak256(&rlp.as_raw());
-        rlp.clear();
+    pub fn sign(&self, key: SecretKey, chain_id: Option<u64>) -> Result<Bytes, SendError> {
+        let hash = tiny_keccak::keccak256(&self.into_raw(chain_id)?.0);
+        let sig = key.sign(&hash[..]).map_err(|_| SendError::Signing)?;
+
+        self.encode(chain_id, Some(sig))
+    }
 
-        let sig = key.sign(&hash[..]).unwrap(); // TODO(nlordell): propagate this error
-        self.rlp_append_signed(&mut rlp, sig, chain_id);
+    pub fn into_raw(&self, chain_id: Option<u64>) -> Result<Bytes, SendError> {
+        self.encode(chain_id, None)
+    }
 
-        rlp.out().into()
+    /// The EIP-2718 type of the transaction, with legacy transactions having
+    /// type 0.
+    pub fn transaction_type(&self) -> u8 {
+        match (&self.gas_pricing, &self.access_list) {
+            (GasPricing::Legacy(_), None) => LEGACY_TRANSACTION_TYPE,
+            (GasPricing::Legacy(_), Some(_)) => EIP2930_TRANSACTION_TYPE,
+            (GasPricing::Eip1559 { .. }, _) => EIP1559_TRANSACTION_TYPE,
+        }
     }
 
-    pub fn into_raw(&self, chain_id: Option<u64>) -> Bytes {
+    fn encode(&self, chain_id: Option<u64>, sig: Option<Signature>) -> Result<Bytes, SendError> {
         let mut rlp = RlpStream::new();
-        self.rlp_append_unsigned(&mut rlp, chain_id);
-        rlp.out().into()
+        let (transaction_type, fields) = match (&self.gas_pricing, &self.access_list) {
+            (GasPricing::Legacy(gas_price), None) => {
+                match sig {
+                    Some(sig) => self.rlp_append_signed(&mut rlp, *gas_price, sig, chain_id),
+                    None => self.rlp_append_unsigned(&mut rlp, *gas_price, chain_id),
+                }
+                return Ok(rlp.out().into());
+            }
+            (GasPricing::Legacy(_), Some(_)) => (EIP2930_TRANSACTION_TYPE, 8),
+            (GasPricing::Eip1559 { .. }, _) => (EIP1559_TRANSACTION_TYPE, 9),
+        };
+
+        // typed transactions always include the chain ID and are encoded as
+        // their type followed by the RLP encoded fields
+        let chain_id = chain_id.ok_or(SendError::MissingChainId)?;
+        rlp.begin_list(if sig.is_some() { fields + 3 } else { fields });
+        rlp.append(&chain_id);
+        rlp.append(&self.nonce);
+        match &self.gas_pricing {
+            GasPricing::Legacy(gas_price) => {
+                rlp.append(gas_price);
+            }
+            GasPricing::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                rlp.append(max_priority_fee_per_gas);
+                rlp.append(max_fee_per_gas);
+            }
+        }
+        rlp.append(&self.gas);
+        self.rlp_append_to(&mut rlp);
+        rlp.append(&self.value);
+        rlp.append(&self.data.0);
+        self.rlp_append_access_list(&mut rlp);
+        if let Some(sig) = sig {
+            // the signature's y-parity is used without any offset
+            rlp.append(&sig.v);
+            rlp.append(&U256::from(sig.r));
+            rlp.append(&U256::from(sig.s));
+        }
+
+        let mut raw = vec![transaction_type];
+        raw.extend(rlp.out());
+        Ok(raw.into())
     }
 
-    fn rlp_append_unsigned(&self, s: &mut RlpStream, chain_id: Option<u64>) {
+    fn rlp_append_unsigned(&self, s: &mut RlpStream, gas_price: U256, chain_id: Option<u64>) {
         s.begin_list(if chain_id.is_some() { 9 } else { 6 });
         s.append(&self.nonce);
-        s.append(&self.gas_price);
+        s.append(&gas_price);
         s.append(&self.gas);
-        s.append(&self.to.unwrap_or_default());
+        self.rlp_append_to(s);
         s.append(&self.value);
         s.append(&self.data.0);
         if let Some(n) = chain_id {
@@ -307,14 +1367,20 @@ impl RawTransaction {
         }
     }
 
-    fn rlp_append_signed(&self, s: &mut RlpStream, sig: Signature, chain_id: Option<u64>) {
+    fn rlp_append_signed(
+        &self,
+        s: &mut RlpStream,
+        gas_price: U256,
+        sig: Signature,
+        chain_id: Option<u64>,
+    ) {
         let v = RawTransaction::add_chain_replay_protection(sig.v as _, chain_id);
 
         s.begin_list(9);
         s.append(&self.nonce);
-        s.append(&self.gas_price);
+        s.append(&gas_price);
         s.append(&self.gas);
-        s.append(&self.to.unwrap_or_default());
+        self.rlp_append_to(s);
         s.append(&self.value);
         s.append(&self.data.0);
         s.append(&v);
@@ -322,6 +1388,32 @@ impl RawTransaction {
         s.append(&U256::from(sig.s));
     }
 
+    fn rlp_append_access_list(&self, s: &mut RlpStream) {
+        let access_list = self
+            .access_list
+            .as_ref()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        s.begin_list(access_list.len());
+        for item in access_list {
+            s.begin_list(2);
+            s.append(&item.address);
+            s.begin_list(item.storage_keys.len());
+            for key in &item.storage_keys {
+                s.append(key);
+            }
+        }
+    }
+
+    fn rlp_append_to(&self, s: &mut RlpStream) {
+        // contract creation transactions have an empty `to` field
+        match &self.to {
+            Some(to) => s.append(to),
+            None => s.append_empty_data(),
+        };
+    }
+
     fn add_chain_replay_protection(v: u64, chain_id: Option<u64>) -> u64 {
         v + if let Some(n) = chain_id {
             35 + n * 2
@@ -330,3 +1422,817 @@ impl RawTransaction {
         }
     }
 }
+
+/// A transaction decoded from its signed raw encoding.
+pub struct SignedTransaction {
+    pub hash: H256,
+    pub transaction: RawTransaction,
+    pub chain_id: Option<u64>,
+    pub signature: Signature,
+}
+
+impl SignedTransaction {
+    /// Decodes a signed legacy, EIP-2930 or EIP-1559 raw transaction. This is
+    /// the inverse of `RawTransaction::sign`.
+    pub fn decode(raw: &[u8]) -> Result<SignedTransaction, DecodeError> {
+        let hash = H256::from(tiny_keccak::keccak256(raw));
+        match raw.first() {
+            // legacy transactions are RLP lists, which start with 0xc0 or more
+            Some(&byte) if byte >= 0xc0 => {
+                let rlp = Rlp::new(raw);
+                expect_length(&rlp, raw.len())?;
+                SignedTransaction::decode_legacy(hash, &rlp)
+            }
+            Some(&transaction_type) => {
+                let rlp = Rlp::new(&raw[1..]);
+                expect_length(&rlp, raw.len() - 1)?;
+                SignedTransaction::decode_typed(hash, transaction_type, &rlp)
+            }
+            None => Err(DecodeError::Empty),
+        }
+    }
+
+    /// Recovers the address of the account that signed the transaction.
+    pub fn sender(&self) -> Result<Address, DecodeError> {
+        let hash = tiny_keccak::keccak256(&self.transaction.into_raw(self.chain_id)?.0);
+        let public = self
+            .signature
+            .recover(&hash)
+            .map_err(|_| DecodeError::InvalidSignature)?;
+
+        Ok(public.address().into())
+    }
+
+    fn decode_legacy(hash: H256, rlp: &Rlp) -> Result<SignedTransaction, DecodeError> {
+        expect_fields(rlp, 9)?;
+        let transaction = RawTransaction {
+            nonce: rlp.val_at(0)?,
+            gas_pricing: GasPricing::Legacy(rlp.val_at(1)?),
+            gas: rlp.val_at(2)?,
+            to: decode_to(&rlp.at(3)?)?,
+            value: rlp.val_at(4)?,
+            data: Bytes(rlp.val_at(5)?),
+            access_list: None,
+        };
+
+        // EIP-155 signatures encode the chain ID in `v`
+        let v: u64 = rlp.val_at(6)?;
+        let (chain_id, y_parity) = match v {
+            v @ 27..=28 => (None, v - 27),
+            v if v >= 35 => (Some((v - 35) / 2), (v - 35) % 2),
+            v => return Err(DecodeError::InvalidV(v)),
+        };
+
+        Ok(SignedTransaction {
+            hash,
+            transaction,
+            chain_id,
+            signature: decode_signature(y_parity as _, rlp.val_at(7)?, rlp.val_at(8)?),
+        })
+    }
+
+    fn decode_typed(
+        hash: H256,
+        transaction_type: u8,
+        rlp: &Rlp,
+    ) -> Result<SignedTransaction, DecodeError> {
+        let (gas_pricing, fields) = match transaction_type {
+            EIP2930_TRANSACTION_TYPE => {
+                expect_fields(rlp, 11)?;
+                (GasPricing::Legacy(rlp.val_at(2)?), 3)
+            }
+            EIP1559_TRANSACTION_TYPE => {
+                expect_fields(rlp, 12)?;
+                let gas_pricing = GasPricing::Eip1559 {
+                    max_priority_fee_per_gas: rlp.val_at(2)?,
+                    max_fee_per_gas: rlp.val_at(3)?,
+                };
+                (gas_pricing, 4)
+            }
+            _ => return Err(DecodeError::UnsupportedType(transaction_type)),
+        };
+
+        let transaction = RawTransaction {
+            nonce: rlp.val_at(1)?,
+            gas_pricing,
+            gas: rlp.val_at(fields)?,
+            to: decode_to(&rlp.at(fields + 1)?)?,
+            value: rlp.val_at(fields + 2)?,
+            data: Bytes(rlp.val_at(fields + 3)?),
+            access_list: Some(decode_access_list(&rlp.at(fields + 4)?)?),
+        };
+
+        // typed transactions encode the signature's y-parity without any
+        // offset, so it can only be 0 or 1
+        let y_parity: u64 = rlp.val_at(fields + 5)?;
+        if y_parity > 1 {
+            return Err(DecodeError::InvalidYParity(y_parity));
+        }
+
+        Ok(SignedTransaction {
+            hash,
+            transaction,
+            chain_id: Some(rlp.val_at(0)?),
+            signature: decode_signature(
+                y_parity as _,
+                rlp.val_at(fields + 6)?,
+                rlp.val_at(fields + 7)?,
+            ),
+        })
+    }
+}
+
+/// Checks that the RLP encoding spans the whole raw transaction, so that
+/// truncated input and trailing bytes are rejected.
+fn expect_length(rlp: &Rlp, actual: usize) -> Result<(), DecodeError> {
+    let encoded = rlp.payload_info()?.total();
+    if encoded != actual {
+        return Err(DecodeError::Length { encoded, actual });
+    }
+    Ok(())
+}
+
+fn expect_fields(rlp: &Rlp, expected: usize) -> Result<(), DecodeError> {
+    let actual = rlp.item_count()?;
+    if actual != expected {
+        return Err(DecodeError::FieldCount { expected, actual });
+    }
+    Ok(())
+}
+
+fn decode_to(rlp: &Rlp) -> Result<Option<Address>, DecoderError> {
+    // contract creation transactions have an empty `to` field
+    if rlp.is_empty() {
+        Ok(None)
+    } else {
+        rlp.as_val().map(Some)
+    }
+}
+
+fn decode_access_list(rlp: &Rlp) -> Result<Vec<AccessListItem>, DecoderError> {
+    rlp.iter()
+        .map(|item| {
+            Ok(AccessListItem {
+                address: item.val_at(0)?,
+                storage_keys: item.list_at(1)?,
+            })
+        })
+        .collect()
+}
+
+fn decode_signature(v: u8, r: U256, s: U256) -> Signature {
+    let mut signature = Signature {
+        v,
+        r: [0; 32],
+        s: [0; 32],
+    };
+    r.to_big_endian(&mut signature.r);
+    s.to_big_endian(&mut signature.s);
+    signature
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("raw transaction is empty")]
+    Empty,
+
+    #[error("unsupported transaction type {0:#04x}")]
+    UnsupportedType(u8),
+
+    #[error("invalid RLP encoding: {0}")]
+    Rlp(#[from] DecoderError),
+
+    #[error("RLP encoding is {encoded} bytes long but the raw transaction has {actual} bytes")]
+    Length { encoded: usize, actual: usize },
+
+    #[error("expected {expected} transaction fields but found {actual}")]
+    FieldCount { expected: usize, actual: usize },
+
+    #[error("invalid signature v value {0}")]
+    InvalidV(u64),
+
+    #[error("invalid signature y-parity {0}")]
+    InvalidYParity(u64),
+
+    #[error("failed to recover the sender from the signature")]
+    InvalidSignature,
+
+    #[error("failed to encode the signed payload: {0}")]
+    Encode(#[from] SendError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::Call;
+    use std::cell::RefCell;
+    use std::collections::{HashMap, VecDeque};
+    use std::rc::Rc;
+    use web3::RequestId;
+
+    fn hex(s: &str) -> Vec<u8> {
+        s.from_hex().unwrap()
+    }
+
+    fn key(s: &str) -> SecretKey {
+        SecretKey::from_raw(&hex(s)).unwrap()
+    }
+
+    fn eip155_example() -> RawTransaction {
+        RawTransaction {
+            nonce: 9.into(),
+            to: Some(Address::from_slice(&[0x35; 20])),
+            value: U256::exp10(18),
+            gas_pricing: GasPricing::Legacy(20_000_000_000u64.into()),
+            gas: 21000.into(),
+            data: Bytes(Vec::new()),
+            access_list: None,
+        }
+    }
+
+    // the transaction from the go-ethereum EIP-2930 tests signed with its
+    // test key
+    fn eip2930_example() -> RawTransaction {
+        RawTransaction {
+            nonce: 3.into(),
+            to: Some(Address::from_slice(&hex(
+                "b94f5374fce5edbc8e2a8697c15331677e6ebf0b",
+            ))),
+            value: 10.into(),
+            gas_pricing: GasPricing::Legacy(1.into()),
+            gas: 25000.into(),
+            data: Bytes(vec![0x55, 0x44]),
+            access_list: Some(Vec::new()),
+        }
+    }
+
+    fn eip1559_example() -> RawTransaction {
+        RawTransaction {
+            gas_pricing: GasPricing::Eip1559 {
+                max_fee_per_gas: 30_000_000_000u64.into(),
+                max_priority_fee_per_gas: 2_000_000_000u64.into(),
+            },
+            gas: 50000.into(),
+            access_list: Some(vec![AccessListItem {
+                address: Address::from_slice(&hex("de0b295669a9fd93d5f28d9ec85e40f4cb697bae")),
+                storage_keys: vec![H256::from_low_u64_be(3), H256::from_low_u64_be(7)],
+            }]),
+            ..eip2930_example()
+        }
+    }
+
+    const EIP155_KEY: &str = "4646464646464646464646464646464646464646464646464646464646464646";
+    const GETH_TEST_KEY: &str = "b71c71a67e1177ad4e901695e1b4b9ee17ae16c6668d313eac2f96dbcda3f291";
+
+    #[test]
+    fn encode_eip155_example() {
+        let tx = eip155_example();
+        assert_eq!(tx.transaction_type(), LEGACY_TRANSACTION_TYPE);
+
+        // signing hash and signed transaction from EIP-155
+        let unsigned = tx.into_raw(Some(1)).unwrap();
+        assert_eq!(
+            tiny_keccak::keccak256(&unsigned.0).to_hex::<String>(),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        assert_eq!(
+            tx.sign(key(EIP155_KEY), Some(1))
+                .unwrap()
+                .0
+                .to_hex::<String>(),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+
+    #[test]
+    fn encode_legacy_without_replay_protection() {
+        assert_eq!(
+            eip155_example()
+                .sign(key(EIP155_KEY), None)
+                .unwrap()
+                .0
+                .to_hex::<String>(),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             801ba08383adc8b8ae116f918fb44ca7ff9dfd8012596a5c130c6246a2cc717ba41cdaa053ddfacf5bd4\
+             aa7e46d1575acf52636ea659b91f29e2fb91c75567a279738f38"
+        );
+    }
+
+    #[test]
+    fn encode_eip2930() {
+        let tx = eip2930_example();
+        assert_eq!(tx.transaction_type(), EIP2930_TRANSACTION_TYPE);
+
+        // signing hash from the go-ethereum EIP-2930 tests
+        let unsigned = tx.into_raw(Some(1)).unwrap();
+        assert_eq!(
+            tiny_keccak::keccak256(&unsigned.0).to_hex::<String>(),
+            "49b486f0ec0a60dfbbca2d30cb07c9e8ffb2a2ff41f29a1ab6737475f6ff69f3"
+        );
+        assert_eq!(
+            tx.sign(key(GETH_TEST_KEY), Some(1))
+                .unwrap()
+                .0
+                .to_hex::<String>(),
+            "01f8630103018261a894b94f5374fce5edbc8e2a8697c15331677e6ebf0b0a825544c080a0bc3aec7c\
+             d7461593b5d40c3c589b46e91f5db03ca4e6f2d9c627c66e861a2480a00e8a0054014dfde54bb9483b5a\
+             bf8a84403ee4367c8fe54666a83efc992e3498"
+        );
+    }
+
+    #[test]
+    fn encode_eip1559() {
+        let tx = eip1559_example();
+        assert_eq!(tx.transaction_type(), EIP1559_TRANSACTION_TYPE);
+        assert_eq!(
+            tx.sign(key(GETH_TEST_KEY), Some(1))
+                .unwrap()
+                .0
+                .to_hex::<String>(),
+            "02f8c9010384773594008506fc23ac0082c35094b94f5374fce5edbc8e2a8697c15331677e6ebf0b0a82\
+             5544f85bf85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a0000000000000000000000000\
+             0000000000000000000000000000000000000003a000000000000000000000000000000000000000000\
+             0000000000000000000000780a03c3cb57b0d38d4ec9595afd73b4fff31b94059f50bacf7b6eace49dd\
+             4baecd18a046560beb75af351ec0737d68380c44f8f0d41b47f0f1d4b9b168257d91aa5924"
+        );
+    }
+
+    #[test]
+    fn typed_transactions_need_chain_id() {
+        for tx in &[eip2930_example(), eip1559_example()] {
+            match tx.into_raw(None) {
+                Err(SendError::MissingChainId) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    fn decode_round_trip(raw: &[u8]) -> SignedTransaction {
+        let tx = SignedTransaction::decode(raw).unwrap();
+        assert_eq!(tx.hash, H256::from(tiny_keccak::keccak256(raw)));
+        let signature = Signature {
+            v: tx.signature.v,
+            r: tx.signature.r,
+            s: tx.signature.s,
+        };
+        assert_eq!(
+            tx.transaction
+                .encode(tx.chain_id, Some(signature))
+                .unwrap()
+                .0,
+            raw
+        );
+        tx
+    }
+
+    #[test]
+    fn decode_signed_transactions() {
+        let eip155_sender = Address::from_slice(&hex("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"));
+        let geth_sender = Address::from_slice(&hex("71562b71999873db5b286df957af199ec94617f7"));
+        let examples = [
+            (eip155_example(), EIP155_KEY, Some(1), eip155_sender),
+            (eip155_example(), EIP155_KEY, None, eip155_sender),
+            (eip2930_example(), GETH_TEST_KEY, Some(1), geth_sender),
+            (eip1559_example(), GETH_TEST_KEY, Some(5), geth_sender),
+        ];
+
+        for (tx, secret, chain_id, sender) in &examples {
+            let raw = tx.sign(key(secret), *chain_id).unwrap();
+            let decoded = decode_round_trip(&raw.0);
+            assert_eq!(decoded.chain_id, *chain_id);
+            assert_eq!(decoded.sender().unwrap(), *sender);
+            assert_eq!(
+                decoded.transaction.transaction_type(),
+                tx.transaction_type()
+            );
+            assert_eq!(decoded.transaction.to, tx.to);
+            assert_eq!(decoded.transaction.data.0, tx.data.0);
+            assert_eq!(
+                decoded.transaction.access_list.map(|list| list.len()),
+                tx.access_list.as_ref().map(Vec::len)
+            );
+        }
+    }
+
+    // mainnet transactions from the alloy-consensus envelope tests
+    #[test]
+    fn decode_live_transactions() {
+        let tx = decode_round_trip(&hex(
+            "f9015482078b8505d21dba0083022ef1947a250d5630b4cf539739df2c5dacb4c659f2488d880c46\
+             549a521b13d8b8e47ff36ab50000000000000000000000000000000000000000000066ab5a608bd00a23\
+             f2fe00000000000000000000000000000000000000000000000000000000000000800000000000000000\
+             0000000048c04ed5691981c42154c6167398f95e8f38a7ff000000000000000000000000000000000000\
+             00000000000000000000632ceac700000000000000000000000000000000000000000000000000000000\
+             00000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2000000000000\
+             0000000000006c6ee5e31d828de241282b9606c8e98ea48526e225a0c9077369501641a92ef7399ff81c\
+             21639ed4fd8fc69cb793cfa1dbfab342e10aa0615facb2f1bcf3274a354cfe384a38d0cc008a11c2dd23\
+             a69111bc6930ba27a8",
+        ));
+        assert_eq!(tx.transaction.transaction_type(), LEGACY_TRANSACTION_TYPE);
+        assert_eq!(tx.chain_id, Some(1));
+        assert_eq!(
+            tx.hash,
+            H256::from_slice(&hex(
+                "280cde7cdefe4b188750e76c888f13bd05ce9a4d7767730feefe8a0e50ca6fc4"
+            ))
+        );
+        assert_eq!(
+            tx.transaction.to,
+            Some(Address::from_slice(&hex(
+                "7a250d5630b4cf539739df2c5dacb4c659f2488d"
+            )))
+        );
+        assert_eq!(
+            tx.sender().unwrap(),
+            Address::from_slice(&hex("a12e1462d0ced572f396f58b6e2d03894cd7c8a4"))
+        );
+
+        let tx = decode_round_trip(&hex(
+            "02f86f0102843b9aca0085029e7822d68298f094d9e1459a7a482635700cbc20bbaf52d495ab9c96\
+             80841b55ba3ac080a0c199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039a0\
+             28ca912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8",
+        ));
+        assert_eq!(tx.transaction.transaction_type(), EIP1559_TRANSACTION_TYPE);
+        assert_eq!(tx.chain_id, Some(1));
+        assert_eq!(
+            tx.transaction.to,
+            Some(Address::from_slice(&hex(
+                "d9e1459a7a482635700cbc20bbaf52d495ab9c96"
+            )))
+        );
+        assert_eq!(
+            tx.sender().unwrap(),
+            Address::from_slice(&hex("001e2b7de757ba469a57bf6b23d982458a07efce"))
+        );
+    }
+
+    #[test]
+    fn decode_rejects_malformed_transactions() {
+        let raw = eip2930_example()
+            .sign(key(GETH_TEST_KEY), Some(1))
+            .unwrap()
+            .0;
+
+        let mut trailing = raw.clone();
+        trailing.push(0);
+        match SignedTransaction::decode(&trailing) {
+            Err(DecodeError::Length { .. }) => {}
+            result => panic!("unexpected result {:?}", result.map(|tx| tx.hash)),
+        }
+        match SignedTransaction::decode(&raw[..raw.len() - 1]) {
+            Err(DecodeError::Length { .. }) => {}
+            result => panic!("unexpected result {:?}", result.map(|tx| tx.hash)),
+        }
+
+        // the y-parity directly precedes the 32 byte r value
+        let mut y_parity = raw.clone();
+        let offset = raw.len() - 2 * 33 - 1;
+        assert_eq!(y_parity[offset], 0x80);
+        y_parity[offset] = 0x02;
+        match SignedTransaction::decode(&y_parity) {
+            Err(DecodeError::InvalidYParity(2)) => {}
+            result => panic!("unexpected result {:?}", result.map(|tx| tx.hash)),
+        }
+
+        let mut unsupported = raw.clone();
+        unsupported[0] = 0x03;
+        match SignedTransaction::decode(&unsupported) {
+            Err(DecodeError::UnsupportedType(3)) => {}
+            result => panic!("unexpected result {:?}", result.map(|tx| tx.hash)),
+        }
+    }
+
+    fn token_abi() -> AbiContract {
+        let function = |name: &str, inputs: &[&str]| {
+            let inputs: Vec<_> = inputs
+                .iter()
+                .map(|kind| serde_json::json!({ "name": "", "type": kind }))
+                .collect();
+            serde_json::json!({
+                "type": "function",
+                "name": name,
+                "inputs": inputs,
+                "outputs": [],
+                "constant": false,
+            })
+        };
+        let abi = serde_json::json!([
+            function("transfer", &["address", "uint256"]),
+            function("safeTransferFrom", &["address", "address", "uint256"]),
+            function(
+                "safeTransferFrom",
+                &["address", "address", "uint256", "bytes"]
+            ),
+        ]);
+
+        AbiContract::load(abi.to_string().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn find_function_by_name_signature_or_selector() {
+        let abi = token_abi();
+        let find = |name: &str| signature(find_function(&abi, name).unwrap());
+
+        assert_eq!(find("transfer"), "transfer(address,uint256)");
+        assert_eq!(
+            find("transfer(address,uint256)"),
+            "transfer(address,uint256)"
+        );
+        assert_eq!(
+            find(" transfer( address, uint256 )"),
+            "transfer(address,uint256)"
+        );
+        assert_eq!(find("0xa9059cbb"), "transfer(address,uint256)");
+        assert_eq!(
+            find("safeTransferFrom(address,address,uint256,bytes)"),
+            "safeTransferFrom(address,address,uint256,bytes)"
+        );
+        assert_eq!(
+            find("0x42842e0e"),
+            "safeTransferFrom(address,address,uint256)"
+        );
+    }
+
+    #[test]
+    fn find_function_errors() {
+        let abi = token_abi();
+
+        for name in &["approve", "transfer(address)", "0x095ea7b3"] {
+            match find_function(&abi, name) {
+                Err(FunctionError::NotFound(missing)) => assert_eq!(missing, *name),
+                result => panic!("unexpected result {:?}", result.map(signature)),
+            }
+        }
+
+        match find_function(&abi, "safeTransferFrom") {
+            Err(FunctionError::Ambiguous {
+                name,
+                mut candidates,
+            }) => {
+                candidates.sort();
+                assert_eq!(name, "safeTransferFrom");
+                assert_eq!(
+                    candidates,
+                    vec![
+                        "safeTransferFrom(address,address,uint256)",
+                        "safeTransferFrom(address,address,uint256,bytes)",
+                    ]
+                );
+            }
+            result => panic!("unexpected result {:?}", result.map(signature)),
+        }
+    }
+
+    /// A transport that answers requests with canned responses for each
+    /// method and records the requests that were made.
+    #[derive(Clone, Debug, Default)]
+    struct TestTransport {
+        responses: Rc<RefCell<HashMap<String, VecDeque<Result<Value, String>>>>>,
+        requests: Rc<RefCell<Vec<(String, Vec<Value>)>>>,
+    }
+
+    impl TestTransport {
+        fn respond(&self, method: &str, response: Result<Value, &str>) {
+            self.responses
+                .borrow_mut()
+                .entry(method.to_string())
+                .or_default()
+                .push_back(response.map_err(String::from));
+        }
+
+        /// Takes the parameters of the requests made for a method so far.
+        fn requests(&self, method: &str) -> Vec<Vec<Value>> {
+            let mut requests = self.requests.borrow_mut();
+            let (matching, other): (Vec<_>, Vec<_>) = requests
+                .drain(..)
+                .partition(|(request, _)| request == method);
+            *requests = other;
+            matching.into_iter().map(|(_, params)| params).collect()
+        }
+    }
+
+    impl Transport for TestTransport {
+        type Out = future::FutureResult<Value, Web3Error>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            let mut requests = self.requests.borrow_mut();
+            requests.push((method.to_string(), params.clone()));
+            let id = requests.len();
+            (id, helpers::build_request(id, method, params))
+        }
+
+        fn send(&self, _: RequestId, request: Call) -> Self::Out {
+            let method = match request {
+                Call::MethodCall(call) => call.method,
+                call => panic!("unexpected call {:?}", call),
+            };
+            let response = self
+                .responses
+                .borrow_mut()
+                .get_mut(&method)
+                .and_then(VecDeque::pop_front)
+                .unwrap_or_else(|| Err(format!("no response for {}", method)));
+            future::result(response.map_err(Web3Error::Transport))
+        }
+    }
+
+    #[test]
+    fn chain_id_must_fit_in_64_bits() {
+        let transport = TestTransport::default();
+        let web3 = Web3::new(transport.clone());
+
+        transport.respond("eth_chainId", Ok("0xffffffffffffffff".into()));
+        assert_eq!(chain_id(&web3).wait().unwrap(), u64::max_value());
+
+        transport.respond("eth_chainId", Ok("0x10000000000000000".into()));
+        match chain_id(&web3).wait() {
+            Err(Web3Error::InvalidResponse(_)) => {}
+            result => panic!("unexpected chain ID {:?}", result),
+        }
+    }
+
+    const TOKEN: &str = "0x5555555555555555555555555555555555555555";
+
+    fn transfer_log(block: u64) -> Value {
+        serde_json::json!({
+            "address": TOKEN,
+            "topics": [
+                "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                "0x0000000000000000000000001111111111111111111111111111111111111111",
+                "0x0000000000000000000000002222222222222222222222222222222222222222",
+            ],
+            "data": format!("0x{:064x}", block * 1000),
+            "blockNumber": format!("0x{:x}", block),
+            "transactionHash": format!("0x{:064x}", block),
+            "logIndex": "0x0",
+        })
+    }
+
+    fn subscribe(transport: &TestTransport, block: u64) -> EventSubscription<TestTransport> {
+        let event = token_abi().event("Transfer").unwrap().clone();
+        let address = Address::from_slice(&hex(&TOKEN[2..]));
+
+        transport.respond("eth_blockNumber", Ok(format!("0x{:x}", block).into()));
+        EventQueryBuilder::new(Web3::new(transport.clone()).eth(), event, address)
+            .subscribe()
+            .wait()
+            .unwrap()
+    }
+
+    fn blocks(logs: &[EventLog<AbiLog>]) -> Vec<Option<u64>> {
+        logs.iter().map(|log| log.block_number).collect()
+    }
+
+    #[test]
+    fn subscription_polls_filter_changes() {
+        let transport = TestTransport::default();
+        transport.respond("eth_newFilter", Ok("0x7".into()));
+        let subscription = subscribe(&transport, 0x10);
+        assert_eq!(subscription.filter_id, Some(7.into()));
+        assert_eq!(subscription.next_block, 0x11);
+
+        // the filter only reports logs from new blocks
+        let filter = &transport.requests("eth_newFilter")[0][0];
+        assert_eq!(filter["fromBlock"], "latest");
+        assert_eq!(filter["toBlock"], "latest");
+        assert_eq!(filter["address"], serde_json::json!([TOKEN]));
+
+        transport.respond(
+            "eth_getFilterChanges",
+            Ok(serde_json::json!([transfer_log(0x11), transfer_log(0x12)])),
+        );
+        let (logs, subscription) = subscription.changes().wait().unwrap();
+        assert_eq!(blocks(&logs), vec![Some(0x11), Some(0x12)]);
+        assert_eq!(
+            logs[0].data.params[2].value,
+            Token::Uint(U256::from(0x11 * 1000))
+        );
+        assert_eq!(subscription.next_block, 0x13);
+        assert_eq!(
+            transport.requests("eth_getFilterChanges"),
+            vec![vec![serde_json::json!("0x7")]]
+        );
+        assert!(transport.requests("eth_getLogs").is_empty());
+
+        // logs from reorged blocks are dropped
+        let mut removed = transfer_log(0x13);
+        removed["removed"] = true.into();
+        transport.respond("eth_getFilterChanges", Ok(serde_json::json!([removed])));
+        let (logs, subscription) = subscription.changes().wait().unwrap();
+        assert!(logs.is_empty());
+        assert_eq!(subscription.next_block, 0x13);
+    }
+
+    #[test]
+    fn subscription_falls_back_to_get_logs() {
+        let transport = TestTransport::default();
+        transport.respond("eth_newFilter", Err("method not found"));
+        let subscription = subscribe(&transport, 0x10);
+        assert_eq!(subscription.filter_id, None);
+
+        transport.respond("eth_blockNumber", Ok("0x12".into()));
+        transport.respond("eth_getLogs", Ok(serde_json::json!([transfer_log(0x12)])));
+        let (logs, subscription) = subscription.changes().wait().unwrap();
+        assert_eq!(blocks(&logs), vec![Some(0x12)]);
+        let filter = &transport.requests("eth_getLogs")[0][0];
+        assert_eq!(filter["fromBlock"], "0x11");
+        assert_eq!(filter["toBlock"], "0x12");
+        assert_eq!(subscription.next_block, 0x13);
+
+        // no logs are queried until a new block is mined
+        transport.respond("eth_blockNumber", Ok("0x12".into()));
+        let (logs, subscription) = subscription.changes().wait().unwrap();
+        assert!(logs.is_empty());
+        assert!(transport.requests("eth_getLogs").is_empty());
+
+        transport.respond("eth_blockNumber", Ok("0x14".into()));
+        transport.respond("eth_getLogs", Ok(serde_json::json!([])));
+        let (logs, subscription) = subscription.changes().wait().unwrap();
+        assert!(logs.is_empty());
+        let filter = &transport.requests("eth_getLogs")[0][0];
+        assert_eq!(filter["fromBlock"], "0x13");
+        assert_eq!(filter["toBlock"], "0x14");
+        assert_eq!(subscription.next_block, 0x15);
+    }
+
+    #[test]
+    fn subscription_resumes_after_filter_is_dropped() {
+        let transport = TestTransport::default();
+        transport.respond("eth_newFilter", Ok("0x7".into()));
+        let subscription = subscribe(&transport, 0x10);
+
+        transport.respond(
+            "eth_getFilterChanges",
+            Ok(serde_json::json!([transfer_log(0x12)])),
+        );
+        let (_, subscription) = subscription.changes().wait().unwrap();
+        assert_eq!(subscription.next_block, 0x13);
+
+        // polling resumes right after the last block logs were seen in, so
+        // that logs are neither reported twice nor missed
+        transport.respond("eth_getFilterChanges", Err("filter not found"));
+        transport.respond("eth_blockNumber", Ok("0x15".into()));
+        transport.respond("eth_getLogs", Ok(serde_json::json!([transfer_log(0x14)])));
+        let (logs, subscription) = subscription.changes().wait().unwrap();
+        assert_eq!(blocks(&logs), vec![Some(0x14)]);
+        let filter = &transport.requests("eth_getLogs")[0][0];
+        assert_eq!(filter["fromBlock"], "0x13");
+        assert_eq!(filter["toBlock"], "0x15");
+        assert_eq!(subscription.filter_id, None);
+        assert_eq!(subscription.next_block, 0x16);
+    }
+
+    #[test]
+    fn subscription_stream() {
+        let transport = TestTransport::default();
+        transport.respond("eth_newFilter", Ok("0x7".into()));
+        let subscription = subscribe(&transport, 0x10);
+
+        for logs in &[
+            serde_json::json!([transfer_log(0x11)]),
+            serde_json::json!([]),
+            serde_json::json!([transfer_log(0x13), transfer_log(0x14)]),
+        ] {
+            transport.respond("eth_getFilterChanges", Ok(logs.clone()));
+        }
+        let logs = subscription
+            .stream(Duration::from_millis(1))
+            .take(3)
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(blocks(&logs), vec![Some(0x11), Some(0x13), Some(0x14)]);
+    }
+
+    #[test]
+    fn query_block_range_and_topics() {
+        let transport = TestTransport::default();
+        let event = token_abi().event("Transfer").unwrap().clone();
+        let address = Address::from_slice(&hex(&TOKEN[2..]));
+        let to = Address::from_slice(&[0x22; 20]);
+
+        transport.respond(
+            "eth_getLogs",
+            Ok(serde_json::json!([transfer_log(0x11), transfer_log(0x12)])),
+        );
+        let logs = EventQueryBuilder::new(Web3::new(transport.clone()).eth(), event, address)
+            .topic1(Topic::This(Token::Address(to)))
+            .from_block(BlockNumber::Number(0x10.into()))
+            .to_block(BlockNumber::Number(0x20.into()))
+            .query()
+            .wait()
+            .unwrap();
+        assert_eq!(blocks(&logs), vec![Some(0x11), Some(0x12)]);
+
+        let filter = &transport.requests("eth_getLogs")[0][0];
+        assert_eq!(filter["fromBlock"], "0x10");
+        assert_eq!(filter["toBlock"], "0x20");
+        // the event signature and the second indexed parameter are filtered on
+        let topics = filter["topics"].to_string();
+        assert!(topics.contains("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"));
+        assert!(topics.contains("0000000000000000000000002222222222222222222222222222222222222222"));
+        assert!(filter["topics"][1].is_null());
+    }
+}
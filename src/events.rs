@@ -0,0 +1,137 @@
+use ethabi::Token;
+use web3::contract::tokens::Detokenize;
+use web3::contract::Error as Web3ContractError;
+use web3::types::{Address, U256};
+
+macro_rules! event {
+    ($name:ident { $($field:ident: $type:ty),* }) => {
+        #[derive(Clone, Debug)]
+        pub struct $name {
+            $(pub $field: $type,)*
+        }
+
+        impl Detokenize for $name {
+            fn from_tokens(tokens: Vec<Token>) -> Result<Self, Web3ContractError> {
+                let ($($field,)*) = Detokenize::from_tokens(tokens)?;
+                Ok($name { $($field,)* })
+            }
+        }
+    };
+}
+
+event!(Transfer {
+    from: Address,
+    to: Address,
+    value: U256
+});
+event!(Deposit {
+    owner: Address,
+    value: U256
+});
+
+/// An ERC20 approval. The spender is not kept since approvals are only ever
+/// queried for the ICO.
+#[derive(Clone, Debug)]
+pub struct Approval {
+    pub owner: Address,
+    pub value: U256,
+}
+
+impl Detokenize for Approval {
+    fn from_tokens(tokens: Vec<Token>) -> Result<Self, Web3ContractError> {
+        let (owner, _spender, value): (Address, Address, U256) = Detokenize::from_tokens(tokens)?;
+        Ok(Approval { owner, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifacts::{ArtifactSource, EmbeddedArtifacts};
+    use crate::contract::EventLog;
+    use ethabi::Event;
+    use web3::types::{Log, H256};
+
+    fn event(contract: &str, name: &str) -> Event {
+        let artifact = EmbeddedArtifacts.load(contract).unwrap();
+        artifact.abi.event(name).unwrap().clone()
+    }
+
+    fn address(byte: u8) -> Address {
+        Address::from_slice(&[byte; 20])
+    }
+
+    fn raw_log(signature: &str, indexed: &[Address], value: u64) -> Log {
+        let mut topics = vec![format!("0x{}", signature)];
+        topics.extend(
+            indexed
+                .iter()
+                .map(|address| format!("{:?}", H256::from(*address))),
+        );
+        serde_json::from_value(serde_json::json!({
+            "address": format!("{:?}", address(0xee)),
+            "topics": topics,
+            "data": format!("0x{:064x}", value),
+            "blockNumber": "0x2a",
+            "transactionHash": format!("0x{:064x}", 1),
+            "logIndex": "0x3",
+        }))
+        .unwrap()
+    }
+
+    const TRANSFER: &str = "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    const APPROVAL: &str = "8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925";
+    const DEPOSIT: &str = "e1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c";
+
+    #[test]
+    fn decode_transfer() {
+        for contract in &["MagicWeth", "Scam"] {
+            let log = raw_log(TRANSFER, &[address(1), address(2)], 1000);
+            let transfer = EventLog::decode(&event(contract, "Transfer"), log)
+                .unwrap()
+                .detokenize::<Transfer>()
+                .unwrap();
+
+            assert_eq!(transfer.data.from, address(1));
+            assert_eq!(transfer.data.to, address(2));
+            assert_eq!(transfer.data.value, 1000.into());
+            assert_eq!(transfer.block_number, Some(42));
+            assert_eq!(transfer.transaction_hash, Some(H256::from_low_u64_be(1)));
+            assert_eq!(transfer.log_index, Some(3.into()));
+        }
+    }
+
+    #[test]
+    fn decode_approval() {
+        let log = raw_log(APPROVAL, &[address(1), address(2)], 5);
+        let approval = EventLog::decode(&event("MagicWeth", "Approval"), log)
+            .unwrap()
+            .detokenize::<Approval>()
+            .unwrap();
+
+        assert_eq!(approval.data.owner, address(1));
+        assert_eq!(approval.data.value, 5.into());
+    }
+
+    #[test]
+    fn decode_deposit() {
+        let log = raw_log(DEPOSIT, &[address(1)], 7);
+        let deposit = EventLog::decode(&event("MagicWeth", "Deposit"), log)
+            .unwrap()
+            .detokenize::<Deposit>()
+            .unwrap();
+
+        assert_eq!(deposit.data.owner, address(1));
+        assert_eq!(deposit.data.value, 7.into());
+    }
+
+    #[test]
+    fn decode_rejects_mismatched_logs() {
+        // a deposit has a single indexed parameter
+        let log = raw_log(DEPOSIT, &[address(1), address(2)], 7);
+        assert!(EventLog::decode(&event("MagicWeth", "Deposit"), log).is_err());
+
+        let log = raw_log(TRANSFER, &[address(1), address(2)], 1000);
+        assert!(EventLog::decode(&event("MagicWeth", "Deposit"), log).is_err());
+    }
+}
@@ -1,27 +1,35 @@
 use std::cmp;
 use std::collections::HashMap;
-use std::io::{self, Result as IoResult, Stdout};
+use std::io::{self, Result as IoResult, Stdout, Write};
+use std::panic::{self, PanicInfo};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use termion::event::Key;
 use termion::input::{MouseTerminal, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
-use termion::screen::AlternateScreen;
+use termion::screen::{AlternateScreen, ToMainScreen};
 use tui::backend::TermionBackend;
 use tui::layout::Rect;
 use tui::style::{Color, Style};
 use tui::widgets::{Block, Borders, Paragraph, Text, Widget};
 use tui::{Frame, Terminal};
 
-type Backend = TermionBackend<AlternateScreen<MouseTerminal<RawTerminal<Stdout>>>>;
+type Backend = TermionBackend<AlternateScreen<MouseTerminal<Stdout>>>;
+type PanicHook = Box<dyn Fn(&PanicInfo) + Sync + Send + 'static>;
 
 pub struct Gui<'a> {
     terminal: Terminal<Backend>,
+    raw_mode: RawMode,
     actions: HashMap<Key, Box<dyn FnMut() -> Control<'a> + 'a>>,
+    tick: Option<(Duration, Box<dyn FnMut() -> bool + 'a>)>,
 }
 
 impl<'a> Gui<'a> {
     pub fn new() -> IoResult<Gui<'a>> {
-        let stdout = io::stdout().into_raw_mode()?;
-        let stdout = MouseTerminal::from(stdout);
+        let raw_mode = RawMode::enable()?;
+        let stdout = MouseTerminal::from(io::stdout());
         let stdout = AlternateScreen::from(stdout);
         let backend = TermionBackend::new(stdout);
 
@@ -30,7 +38,9 @@ impl<'a> Gui<'a> {
 
         Ok(Gui {
             terminal,
+            raw_mode,
             actions: HashMap::new(),
+            tick: None,
         })
     }
 
@@ -42,20 +52,47 @@ impl<'a> Gui<'a> {
         self
     }
 
+    /// Sets a callback that is called periodically while waiting for input.
+    /// The view is redrawn whenever the callback returns `true`.
+    pub fn with_tick<F>(mut self, interval: Duration, tick: F) -> Self
+    where
+        F: FnMut() -> bool + 'a,
+    {
+        self.tick = Some((interval, Box::new(tick)));
+        self
+    }
+
     pub fn run<F>(self, mut draw: F) -> IoResult<i32>
     where
         F: FnMut(Frame<'_, Backend>),
     {
         let Gui {
             mut terminal,
+            raw_mode: _raw_mode,
             mut actions,
+            mut tick,
         } = self;
 
-        let mut keys = io::stdin().keys();
+        let keys = keys();
 
         terminal.draw(|f| draw(f))?;
-        while let Some(key) = keys.next() {
-            let key = key?;
+        loop {
+            let key = match &mut tick {
+                Some((interval, tick)) => match keys.recv_timeout(*interval) {
+                    Ok(key) => key?,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if tick() {
+                            terminal.draw(|f| draw(f))?;
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Ok(0),
+                },
+                None => match keys.recv() {
+                    Ok(key) => key?,
+                    Err(_) => return Ok(0),
+                },
+            };
             if let Key::Esc = key {
                 return Ok(0);
             }
@@ -77,7 +114,11 @@ impl<'a> Gui<'a> {
                                     .render(&mut f, center(size, (18, 3)));
                             })?;
 
-                            match keys.next().unwrap()? {
+                            let key = match keys.recv() {
+                                Ok(key) => key?,
+                                Err(_) => return Ok(0),
+                            };
+                            match key {
                                 Key::Esc => return Ok(0),
                                 Key::Char('\n') => break,
                                 Key::Char(c) => input.push(c),
@@ -96,11 +137,85 @@ impl<'a> Gui<'a> {
                 }
             }
         }
+    }
+}
+
+/// Keeps the terminal in raw mode for as long as it is alive. While enabled,
+/// a panic hook restores the terminal before the panic message is printed so
+/// that it is not lost on the alternate screen.
+struct RawMode {
+    raw: Arc<Mutex<Option<RawTerminal<Stdout>>>>,
+    previous_hook: Arc<PanicHook>,
+}
+
+impl RawMode {
+    fn enable() -> IoResult<RawMode> {
+        let raw = Arc::new(Mutex::new(Some(io::stdout().into_raw_mode()?)));
+        let previous_hook = Arc::new(panic::take_hook());
+
+        let (hook_raw, hook_previous) = (raw.clone(), previous_hook.clone());
+        panic::set_hook(Box::new(move |info| {
+            restore_raw_mode(&hook_raw);
+            let mut stdout = io::stdout();
+            let _ = write!(stdout, "{}{}", ToMainScreen, termion::cursor::Show);
+            let _ = stdout.flush();
+            hook_previous(info);
+        }));
+
+        Ok(RawMode { raw, previous_hook })
+    }
+}
 
-        unreachable!();
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // reinstall the hook that was replaced, the hook can't be changed
+        // while panicking but it has already restored the terminal by then
+        if !thread::panicking() {
+            let previous_hook = self.previous_hook.clone();
+            panic::set_hook(Box::new(move |info| previous_hook(info)));
+        }
+        restore_raw_mode(&self.raw);
     }
 }
 
+/// Leaves raw mode by dropping the raw terminal, which restores the original
+/// terminal settings.
+fn restore_raw_mode(raw: &Mutex<Option<RawTerminal<Stdout>>>) {
+    let raw = match raw.lock() {
+        Ok(mut raw) => raw.take(),
+        Err(poisoned) => poisoned.into_inner().take(),
+    };
+    drop(raw);
+}
+
+/// Reads keys from stdin on a separate thread so that waiting for input does
+/// not block periodic updates.
+fn keys() -> Receiver<IoResult<Key>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for key in io::stdin().keys() {
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Prompts for a password on the terminal without echoing the input. This must
+/// be called before the GUI takes over the terminal.
+pub fn prompt_password(prompt: &str) -> IoResult<String> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()?;
+
+    let password = io::stdin().read_passwd(&mut stdout)?;
+    writeln!(stdout)?;
+
+    Ok(password.unwrap_or_default())
+}
+
 fn center(size: Rect, (w, h): (u16, u16)) -> Rect {
     let (w, h) = (cmp::min(size.width, w), cmp::min(size.height, h));
     let x = size.x + ((size.width - w) / 2);
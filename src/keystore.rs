@@ -0,0 +1,427 @@
+use crate::scrypt;
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+use ethsign::SecretKey;
+use hmac::Hmac;
+use rustc_hex::{FromHex, ToHex};
+use serde::de::{Deserializer, Error as _};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use sha2::Sha256;
+use std::fs::{File, OpenOptions};
+use std::io::Error as IoError;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use thiserror::Error;
+use web3::types::Address;
+
+const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// The largest scrypt cost `128 * n * r * p` accepted when decrypting key
+/// files, which bounds both the memory used by the `n * r` sized scratch
+/// buffer and the time spent filling it `p` times. Key files created by geth
+/// and by this client are exactly at the limit.
+const SCRYPT_MAX_COST: u64 = 256 << 20;
+const DKLEN: usize = 32;
+
+/// A Web3 Secret Storage (V3) encrypted key file as used by geth and MetaMask.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyFile {
+    pub version: u64,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<HexBytes>,
+    #[serde(alias = "Crypto")]
+    pub crypto: Crypto,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Crypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: HexBytes,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    pub mac: HexBytes,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CipherParams {
+    pub iv: HexBytes,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        r: u32,
+        p: u32,
+        salt: HexBytes,
+    },
+    Pbkdf2 {
+        dklen: usize,
+        c: usize,
+        prf: String,
+        salt: HexBytes,
+    },
+}
+
+impl KeyFile {
+    pub fn load<P>(path: P) -> Result<KeyFile, KeystoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let json = File::open(path)?;
+        let key_file: KeyFile = serde_json::from_reader(json)?;
+        if key_file.version != 3 {
+            return Err(KeystoreError::UnsupportedVersion(key_file.version));
+        }
+
+        Ok(key_file)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<(), KeystoreError>
+    where
+        P: AsRef<Path>,
+    {
+        // key files are secrets, never clobber an existing one and keep them
+        // readable by the owner only
+        let json = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        serde_json::to_writer_pretty(json, self)?;
+
+        Ok(())
+    }
+
+    pub fn encrypt(secret: &SecretKey, password: &str) -> Result<KeyFile, KeystoreError> {
+        let salt = rand::random::<[u8; 32]>().to_vec();
+        let iv = rand::random::<[u8; 16]>().to_vec();
+
+        let kdfparams = KdfParams::Scrypt {
+            dklen: DKLEN,
+            n: 1 << SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: HexBytes(salt),
+        };
+        let key = kdfparams.derive_key(password)?;
+
+        let mut ciphertext = secret.private().to_vec();
+        aes_128_ctr(&key[..16], &iv, &mut ciphertext);
+        let mac = mac(&key, &ciphertext);
+
+        Ok(KeyFile {
+            version: 3,
+            id: uuid_v4(),
+            address: Some(HexBytes(secret.public().address().to_vec())),
+            crypto: Crypto {
+                cipher: "aes-128-ctr".to_string(),
+                cipherparams: CipherParams { iv: HexBytes(iv) },
+                ciphertext: HexBytes(ciphertext),
+                kdf: "scrypt".to_string(),
+                kdfparams,
+                mac: HexBytes(mac.to_vec()),
+            },
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<SecretKey, KeystoreError> {
+        let crypto = &self.crypto;
+        if crypto.cipher != "aes-128-ctr" {
+            return Err(KeystoreError::UnsupportedCipher(crypto.cipher.clone()));
+        }
+        if crypto.cipherparams.iv.0.len() != 16 {
+            return Err(KeystoreError::InvalidCipherParams);
+        }
+        if crypto.mac.0.len() != 32 {
+            return Err(KeystoreError::CorruptedMac);
+        }
+
+        let key = match (crypto.kdf.as_str(), &crypto.kdfparams) {
+            ("scrypt", KdfParams::Scrypt { .. }) | ("pbkdf2", KdfParams::Pbkdf2 { .. }) => {
+                crypto.kdfparams.derive_key(password)?
+            }
+            ("scrypt", _) | ("pbkdf2", _) => return Err(KeystoreError::InvalidKdfParams),
+            (kdf, _) => return Err(KeystoreError::UnsupportedKdf(kdf.to_string())),
+        };
+        if mac(&key, &crypto.ciphertext.0)[..] != crypto.mac.0[..] {
+            return Err(KeystoreError::InvalidPassword);
+        }
+
+        let mut plaintext = crypto.ciphertext.0.clone();
+        aes_128_ctr(&key[..16], &crypto.cipherparams.iv.0, &mut plaintext);
+        let secret = SecretKey::from_raw(&plaintext).map_err(|_| KeystoreError::InvalidKey)?;
+
+        let actual: Address = secret.public().address().into();
+        match self.address() {
+            Some(expected) if expected != actual => {
+                Err(KeystoreError::AddressMismatch { expected, actual })
+            }
+            _ => Ok(secret),
+        }
+    }
+
+    pub fn address(&self) -> Option<Address> {
+        match &self.address {
+            Some(address) if address.0.len() == 20 => Some(Address::from_slice(&address.0)),
+            _ => None,
+        }
+    }
+}
+
+impl KdfParams {
+    fn derive_key(&self, password: &str) -> Result<Vec<u8>, KeystoreError> {
+        match self {
+            KdfParams::Scrypt {
+                dklen,
+                n,
+                r,
+                p,
+                salt,
+            } => {
+                if *dklen < DKLEN || !n.is_power_of_two() || *n < 2 || *r == 0 || *p == 0 {
+                    return Err(KeystoreError::InvalidKdfParams);
+                }
+                let cost = n
+                    .checked_mul(u64::from(*r))
+                    .and_then(|cost| cost.checked_mul(u64::from(*p)))
+                    .and_then(|cost| cost.checked_mul(128));
+                match cost {
+                    Some(cost) if cost <= SCRYPT_MAX_COST => {}
+                    _ => return Err(KeystoreError::InvalidKdfParams),
+                }
+                let (n, r, p) = (*n as usize, *r as usize, *p as usize);
+
+                let mut key = vec![0; *dklen];
+                scrypt::scrypt(password.as_bytes(), &salt.0, n, r, p, &mut key);
+                Ok(key)
+            }
+            KdfParams::Pbkdf2 {
+                dklen,
+                c,
+                prf,
+                salt,
+            } => {
+                if prf != "hmac-sha256" {
+                    return Err(KeystoreError::UnsupportedKdf(prf.clone()));
+                }
+                if *dklen < DKLEN || *c == 0 {
+                    return Err(KeystoreError::InvalidKdfParams);
+                }
+
+                let mut key = vec![0; *dklen];
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt.0, *c, &mut key);
+                Ok(key)
+            }
+        }
+    }
+}
+
+fn aes_128_ctr(key: &[u8], iv: &[u8], data: &mut [u8]) {
+    let mut cipher = Aes128Ctr::new(GenericArray::from_slice(key), GenericArray::from_slice(iv));
+    cipher.apply_keystream(data);
+}
+
+fn mac(key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut data = key[16..32].to_vec();
+    data.extend_from_slice(ciphertext);
+    tiny_keccak::keccak256(&data)
+}
+
+fn uuid_v4() -> String {
+    let mut bytes = rand::random::<[u8; 16]>();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.to_hex();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[derive(Debug)]
+pub struct HexBytes(pub Vec<u8>);
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        let hex = hex.trim_start_matches("0x");
+        hex.from_hex()
+            .map(HexBytes)
+            .map_err(|err| D::Error::custom(format!("invalid hex string: {}", err)))
+    }
+}
+
+impl Serialize for HexBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0.to_hex::<String>())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("failed to access key file: {0}")]
+    Io(#[from] IoError),
+
+    #[error("failed to parse key file JSON")]
+    Json(#[from] JsonError),
+
+    #[error("unsupported key file version {0}")]
+    UnsupportedVersion(u64),
+
+    #[error("unsupported key file cipher '{0}'")]
+    UnsupportedCipher(String),
+
+    #[error("unsupported key file key derivation function '{0}'")]
+    UnsupportedKdf(String),
+
+    #[error("invalid key file cipher parameters")]
+    InvalidCipherParams,
+
+    #[error("invalid key file key derivation parameters")]
+    InvalidKdfParams,
+
+    #[error("key file MAC is corrupted")]
+    CorruptedMac,
+
+    #[error("invalid password for key file")]
+    InvalidPassword,
+
+    #[error("key file contains an invalid secret key")]
+    InvalidKey,
+
+    #[error("key file is for address {expected:?} but decrypted key is for {actual:?}")]
+    AddressMismatch { expected: Address, actual: Address },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::os::unix::fs::PermissionsExt;
+
+    // test vectors from the Web3 Secret Storage definition
+    const PBKDF2_KEY_FILE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+    const SCRYPT_KEY_FILE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "r": 1,
+                "p": 8,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+    const PASSWORD: &str = "testpassword";
+    const SECRET: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    fn decrypt(json: &str, password: &str) -> Result<SecretKey, KeystoreError> {
+        serde_json::from_str::<KeyFile>(json)
+            .unwrap()
+            .decrypt(password)
+    }
+
+    #[test]
+    fn decrypt_pbkdf2_test_vector() {
+        let secret = decrypt(PBKDF2_KEY_FILE, PASSWORD).unwrap();
+        assert_eq!(secret.private().to_hex::<String>(), SECRET);
+    }
+
+    #[test]
+    fn decrypt_scrypt_test_vector() {
+        let secret = decrypt(SCRYPT_KEY_FILE, PASSWORD).unwrap();
+        assert_eq!(secret.private().to_hex::<String>(), SECRET);
+    }
+
+    #[test]
+    fn reject_expensive_scrypt_params() {
+        for params in &[
+            r#""n": 262144, "r": 1, "p": 9"#,
+            r#""n": 524288, "r": 8, "p": 1"#,
+            r#""n": 2, "r": 1, "p": 4294967295"#,
+            r#""n": 9223372036854775808, "r": 8, "p": 1"#,
+            r#""n": 1048576, "r": 4294967295, "p": 4294967295"#,
+        ] {
+            let key_file = SCRYPT_KEY_FILE.replace(
+                r#""n": 262144,
+                "r": 1,
+                "p": 8"#,
+                params,
+            );
+            assert_ne!(key_file, SCRYPT_KEY_FILE);
+            match decrypt(&key_file, PASSWORD) {
+                Err(KeystoreError::InvalidKdfParams) => {}
+                result => panic!("unexpected decryption result {:?}", result.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn decrypt_with_wrong_password() {
+        match decrypt(PBKDF2_KEY_FILE, "wrongpassword") {
+            Err(KeystoreError::InvalidPassword) => {}
+            result => panic!("unexpected decryption result {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn save_is_private_and_never_overwrites() {
+        let key_file: KeyFile = serde_json::from_str(PBKDF2_KEY_FILE).unwrap();
+        let path = std::env::temp_dir().join(format!("scam-ico-{}.json", uuid_v4()));
+
+        key_file.save(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let overwrite = key_file.save(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        match overwrite {
+            Err(KeystoreError::Io(err)) => assert_eq!(err.kind(), ErrorKind::AlreadyExists),
+            result => panic!("unexpected save result {:?}", result),
+        }
+    }
+}
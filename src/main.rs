@@ -1,27 +1,57 @@
+mod activity;
+mod amount;
+mod artifacts;
+#[cfg(test)]
+mod bindgen;
+mod bindings;
+mod bip32;
 mod context;
 mod contract;
+mod events;
 mod gui;
+mod keystore;
+mod revert;
+mod scrypt;
+mod transaction;
 mod truffle;
 mod wallet;
 
-use crate::context::{Context, State};
-use crate::gui::{Control, Gui};
-use crate::wallet::Wallet;
+use crate::activity::{Activity, ActivityEvent};
+use crate::amount::Amount;
+use crate::artifacts::{ArtifactSource, EmbeddedArtifacts, FallbackArtifacts};
+use crate::bip32::PathTemplate;
+use crate::context::{self, Context, ContextError, GasOptions, State, ICO_TARGET};
+use crate::gui::{self, Control, Gui};
+use crate::transaction::WaitError;
+use crate::wallet::{Keychain, PrivateKeySource, Wallet, WalletError};
 use bip39::{Language, Mnemonic};
+use rustc_hex::FromHex;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::path::PathBuf;
+use std::process;
 use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 use termion::event::Key;
 use tui::layout::{Alignment, Constraint, Direction, Layout};
 use tui::style::{Color, Modifier, Style};
 use tui::widgets::{Block, Borders, Paragraph, SelectableList, Text, Widget};
+use web3::futures::future::Either;
 use web3::futures::Future;
 use web3::transports::Http;
 use web3::types::Address;
 use web3::Web3;
 
+/// The number of events kept in the activity feed.
+const ACTIVITY_FEED_LENGTH: usize = 100;
+
+/// The interval at which the node is polled for new activity.
+const ACTIVITY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "scam-ico", about = "Scam ICO Client.")]
 struct Opt {
@@ -29,15 +59,34 @@ struct Opt {
     #[structopt(short, long, default_value = "http://localhost:7545")]
     transport: String,
 
-    /// Path to truffle project.
-    #[structopt(short = "p", long, default_value = ".")]
-    truffle_project: PathBuf,
+    /// Path to the truffle, Hardhat or Foundry project to load contract
+    /// artifacts from. The kind of project is detected automatically. If it is
+    /// not specified, artifacts are loaded from the current directory with the
+    /// artifacts embedded in the binary as a fallback.
+    #[structopt(short = "p", long)]
+    truffle_project: Option<PathBuf>,
+
+    /// The key of the network in the truffle artifact to use the Scam ICO
+    /// deployment of. If it is not specified the network is resolved from the
+    /// node's network and chain IDs.
+    #[structopt(short, long)]
+    network: Option<String>,
 
     /// The Scam ICO contract address. If it is not specified the address in the
     /// truffle artifact will be used.
     #[structopt(short, long)]
     contract: Option<Address>,
 
+    /// Deploy a new Scam ICO contract for the specified WETH contract address
+    /// instead of using an existing one. It is deployed from the --deployer
+    /// account, or the first account of the wallet that can send transactions.
+    #[structopt(long, conflicts_with = "contract")]
+    deploy: Option<Address>,
+
+    /// The wallet account to deploy the Scam ICO contract from with --deploy.
+    #[structopt(long, requires = "deploy")]
+    deployer: Option<Address>,
+
     /// The BIP-0039 mnemonic to use for generating BIP-0043 accounts. If it is
     /// not specified then it will use web3 to get the list of accounts and for
     /// signing.
@@ -48,6 +97,105 @@ struct Opt {
     /// otherwise
     #[structopt(long, default_value = "3")]
     accounts: usize,
+
+    /// The BIP-0032 derivation path template for mnemonic accounts. Can be one
+    /// of 'bip44' (m/44'/60'/0'/0/{i}), 'legacy' (m/44'/60'/0'/{i}),
+    /// 'ledger-live' (m/44'/60'/{i}'/0/0) or a custom path where '{i}' is
+    /// replaced with the account index.
+    #[structopt(long, default_value = "bip44")]
+    derivation_path: PathTemplate,
+
+    /// The optional BIP-0039 passphrase to use along with the mnemonic.
+    #[structopt(long, default_value = "")]
+    passphrase: String,
+
+    /// Discover mnemonic accounts by scanning indices until a gap of unused
+    /// accounts is found instead of generating a fixed number of accounts.
+    #[structopt(long)]
+    discover: bool,
+
+    /// The number of consecutive unused accounts that ends account discovery.
+    #[structopt(long, default_value = "20")]
+    gap_limit: usize,
+
+    /// Web3 Secret Storage (V3) key files to load accounts from. The password
+    /// for each key file is prompted for on startup.
+    #[structopt(long)]
+    keystore: Vec<PathBuf>,
+
+    /// Export all accounts with private keys to encrypted key files in the
+    /// specified directory and exit.
+    #[structopt(long)]
+    export_keystore: Option<PathBuf>,
+
+    /// Files containing hex encoded private keys, one per line.
+    #[structopt(long)]
+    private_key_file: Vec<PathBuf>,
+
+    /// Environment variables containing hex encoded private keys.
+    #[structopt(long)]
+    private_key_env: Vec<String>,
+
+    /// Prompt for a hex encoded private key on startup.
+    #[structopt(long)]
+    private_key_prompt: bool,
+
+    /// Include the node's accounts even when other account sources are
+    /// specified. Node accounts are always used when no other sources are.
+    #[structopt(long)]
+    node_accounts: bool,
+
+    /// Addresses to watch. Balances are shown for watch-only accounts but they
+    /// can not be used to send transactions.
+    #[structopt(long)]
+    watch: Vec<Address>,
+
+    /// The number of block confirmations to wait for after a transaction is
+    /// mined.
+    #[structopt(long, default_value = "0")]
+    confirmations: usize,
+
+    /// How long to wait in seconds for a transaction to be mined and confirmed
+    /// before giving up.
+    #[structopt(long, default_value = "600")]
+    timeout: u64,
+
+    /// The gas price in gwei of legacy transactions. It is queried from the
+    /// node if it is not specified.
+    #[structopt(
+        long,
+        conflicts_with_all = &["eip1559", "max-fee-per-gas", "max-priority-fee-per-gas"]
+    )]
+    gas_price: Option<Amount>,
+
+    /// Send EIP-1559 transactions instead of legacy transactions. Fees that are
+    /// not specified are estimated from the node's suggested priority fee and
+    /// the base fee of the latest block.
+    #[structopt(long)]
+    eip1559: bool,
+
+    /// The maximum fee per gas in gwei, including the base fee, of EIP-1559
+    /// transactions. Implies --eip1559.
+    #[structopt(long)]
+    max_fee_per_gas: Option<Amount>,
+
+    /// The maximum priority fee per gas in gwei paid to the miner of EIP-1559
+    /// transactions. Implies --eip1559.
+    #[structopt(long)]
+    max_priority_fee_per_gas: Option<Amount>,
+
+    /// Fund the ICO with EIP-2930 transactions with an access list generated
+    /// by the node with `eth_createAccessList`. The gas the funding is
+    /// estimated to use with the access list is reported next to the estimate
+    /// without it.
+    #[structopt(long)]
+    access_list: bool,
+
+    /// Decode a hex encoded signed raw transaction, showing its sender and the
+    /// Scam ICO, WETH or SCM function it calls, and exit. The transaction is
+    /// decoded offline with the embedded contract ABIs.
+    #[structopt(long, conflicts_with = "deploy")]
+    decode: Option<RawTransactionArg>,
 }
 
 #[derive(Debug)]
@@ -68,39 +216,224 @@ impl MnemonicArg {
     }
 }
 
+#[derive(Debug)]
+pub struct RawTransactionArg(Vec<u8>);
+
+impl FromStr for RawTransactionArg {
+    type Err = Box<dyn Error + 'static>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = s.trim().trim_start_matches("0x").from_hex()?;
+        Ok(RawTransactionArg(raw))
+    }
+}
+
+impl RawTransactionArg {
+    fn as_inner(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 fn main() {
     let opt = Opt::from_args();
 
-    let (eloop, http) = Http::new(&opt.transport).expect("error setting up transport");
+    // the terminal is restored by the time `run` returns, so errors can be
+    // printed normally
+    if let Err(err) = run(opt) {
+        eprintln!("Error: {}", err);
+        if let Some(hint) = hint(&err) {
+            eprintln!("Hint: {}", hint);
+        }
+        process::exit(1);
+    }
+}
+
+/// Suggests how to fix the configuration for errors that are commonly caused
+/// by incorrect command line options.
+fn hint(err: &ContextError) -> Option<&'static str> {
+    match err {
+        ContextError::Artifact(_) => Some(
+            "specify the project to load contract artifacts from with --truffle-project \
+             or the Scam ICO address with --contract",
+        ),
+        ContextError::Network(_) => Some(
+            "select the deployment to use with --network or specify the Scam ICO address \
+             with --contract",
+        ),
+        ContextError::Web3(_) => Some("check that a node is reachable with the --transport URL"),
+        ContextError::Wait(WaitError::Timeout { .. }) => Some(
+            "the transaction may still be mined, raise the fees with --gas-price or \
+             --max-fee-per-gas to get transactions mined sooner or wait longer with --timeout",
+        ),
+        ContextError::Wallet(_) => {
+            Some("check the --mnemonic, --keystore and --private-key-* options")
+        }
+        _ => None,
+    }
+}
+
+fn run(opt: Opt) -> Result<(), ContextError> {
+    if let Some(raw) = &opt.decode {
+        let transaction = context::decode_transaction(raw.as_inner(), &EmbeddedArtifacts)?;
+        println!("{}", transaction);
+        return Ok(());
+    }
+
+    let (eloop, http) = Http::new(&opt.transport)?;
     eloop.into_remote();
     let web3 = Web3::new(http);
 
-    let wallet = if let Some(mnemonic) = &opt.mnemonic {
-        Wallet::with_mnemonic(mnemonic.as_inner(), opt.accounts)
+    let artifacts: Box<dyn ArtifactSource> = match &opt.truffle_project {
+        Some(project) => artifacts::detect(project),
+        None => Box::new(FallbackArtifacts(artifacts::detect("."), EmbeddedArtifacts)),
+    };
+    let gwei = |fee: Option<Amount>| {
+        fee.map(|fee| fee.rescale(9).map(|fee| fee.raw()))
+            .transpose()
+    };
+    let gas = GasOptions {
+        gas_price: gwei(opt.gas_price)?,
+        eip1559: opt.eip1559,
+        max_fee_per_gas: gwei(opt.max_fee_per_gas)?,
+        max_priority_fee_per_gas: gwei(opt.max_priority_fee_per_gas)?,
+    };
+    // the wallet is loaded before deploying so that the contract can be
+    // deployed from any of its accounts, otherwise it is loaded once the
+    // contracts are available to discover mnemonic accounts with
+    let (context, wallet) = if let Some(weth_address) = opt.deploy {
+        let wallet = load_wallet(&opt, &web3, None)?;
+        let deployer = match opt.deployer {
+            Some(address) => wallet
+                .accounts()
+                .find(|account| account.address() == address)
+                .ok_or(ContextError::UnknownDeployer(address))?,
+            None => wallet
+                .accounts()
+                .find(|account| !account.is_watch_only())
+                .ok_or(ContextError::NoAccounts)?,
+        };
+        let context =
+            Context::deploy(web3.clone(), &*artifacts, weth_address, deployer, gas).wait()?;
+        (context, Some(wallet))
+    } else if let Some(ico_address) = opt.contract {
+        let context = Context::with_ico_address(web3.clone(), &*artifacts, ico_address).wait()?;
+        (context, None)
     } else {
-        Wallet::local(web3.clone())
-            .wait()
-            .expect("failed to get local accounts")
+        let context = Context::new(web3.clone(), &*artifacts, opt.network.clone()).wait()?;
+        (context, None)
     };
+    let context = context
+        .confirmations(opt.confirmations)
+        .timeout(Duration::from_secs(opt.timeout))
+        .gas_options(gas)
+        .access_lists(opt.access_list);
 
-    let context = if let Some(ico_address) = opt.contract {
-        Context::with_ico_address(web3.clone(), &opt.truffle_project, ico_address)
-            .wait()
-            .expect("failed to load context as specified address")
-    } else {
-        Context::new(web3.clone(), &opt.truffle_project)
-            .wait()
-            .expect("failed to deploy ico contract and load context")
+    let wallet = match wallet {
+        Some(wallet) => wallet,
+        None => load_wallet(&opt, &web3, Some(&context))?,
     };
 
+    if let Some(dir) = &opt.export_keystore {
+        let password = gui::prompt_password("New key file password: ")?;
+        let confirmation = gui::prompt_password("Repeat password: ")?;
+        if password != confirmation {
+            return Err(ContextError::PasswordMismatch);
+        }
+
+        for path in wallet.export_keystores(dir, &password)? {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    let accounts: Vec<_> = wallet.accounts().collect();
+    if accounts.is_empty() {
+        return Err(ContextError::NoAccounts);
+    }
     let account_selection = RefCell::new(0usize);
-    let naccounts = wallet.accounts().count();
+    let naccounts = accounts.len();
+    let selected_account = || accounts[*account_selection.borrow()];
+    // warn about contracts that don't have the expected code, since the ICO
+    // might be a scam (well, even more than it already is)
+    // the warnings are shown in their own panel for as long as the client runs
+    // so that they can't be missed or overwritten by the status
+    let warnings: Vec<String> = match context.verify_code(&*artifacts).wait() {
+        Ok(mismatches) => mismatches
+            .iter()
+            .map(|mismatch| mismatch.to_string())
+            .collect(),
+        Err(err) => vec![format!("failed to verify contract code: {}", err)],
+    };
+    let status = RefCell::new(String::new());
+    // the outcomes are formatted by the caller since funding also reports the
+    // access list gas estimates
+    let report = |result: Result<String, ContextError>| {
+        status.replace(match result {
+            Ok(outcome) => format!("The {}", outcome),
+            Err(err) => format!("Error: {}", err),
+        });
+    };
+    let refuse_watch_only = || {
+        let account = selected_account();
+        if account.is_watch_only() {
+            report(Err(ContextError::WatchOnly(account.address())));
+            true
+        } else {
+            false
+        }
+    };
+
+    // the leaderboard queries all past contributions, so it is cached and only
+    // refreshed when there is new activity or the view is refreshed
+    let leaderboard = RefCell::new(None);
+    let refresh_leaderboard = || {
+        leaderboard.replace(None);
+    };
+
+    // the activity feed is streamed on a separate thread so that waiting for
+    // the node doesn't block input, re-subscribing whenever the stream fails
+    let activity = RefCell::new(watch_activity(context.activity()));
+    let feed = RefCell::new(VecDeque::new());
+    let poll_activity = || {
+        let mut events = Vec::new();
+        loop {
+            let event = activity.borrow().try_recv();
+            match event {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    activity.replace(watch_activity(context.activity()));
+                    break;
+                }
+            }
+        }
+        if events.is_empty() {
+            return false;
+        }
+
+        events.sort_by_key(ActivityEvent::position);
+        let mut feed = feed.borrow_mut();
+        for event in &events {
+            feed.push_front(event.to_string());
+        }
+        feed.truncate(ACTIVITY_FEED_LENGTH);
+        refresh_leaderboard();
+        true
+    };
+
+    let show_leaderboard = RefCell::new(false);
 
     use Control::*;
-    Gui::new()
-        .expect("failed to setup terminal")
+    Gui::new()?
         .with_action(Key::Char('q'), || Quit(0))
-        .with_action(Key::F(5), || Continue)
+        .with_action(Key::F(5), || {
+            refresh_leaderboard();
+            Continue
+        })
+        .with_action(Key::Char('l'), || {
+            show_leaderboard.replace_with(|&mut v| !v);
+            Continue
+        })
         .with_action(Key::Up, || {
             account_selection.replace_with(|&mut v| match v {
                 0 => naccounts - 1,
@@ -112,41 +445,94 @@ fn main() {
             account_selection.replace_with(|&mut v| (v + 1) % naccounts);
             Continue
         })
-        .with_action(Key::Char('s'), || Input(Box::new(|input| {
-            let amount: f64 = match input.parse() {
-                Ok(a) => a,
-                Err(_) => return,
-            };
-            let account = wallet.accounts().nth(*account_selection.borrow()).unwrap();
-            let _ = context.purchase_weth(account, amount).wait();
-        })))
-        .with_action(Key::Char('d'), || Input(Box::new(|input| {
-            let amount: f64 = match input.parse() {
-                Ok(a) => a,
-                Err(_) => return,
-            };
-            let account = wallet.accounts().nth(*account_selection.borrow()).unwrap();
-            let _ = context.magic_weth(account, amount).wait();
-        })))
-        .with_action(Key::Char('f'), || Input(Box::new(|input| {
-            let amount: f64 = match input.parse() {
-                Ok(a) => a,
-                Err(_) => return,
-            };
-            let account = wallet.accounts().nth(*account_selection.borrow()).unwrap();
-            let _ = context.fund(account, amount).wait();
-        })))
+        .with_action(Key::Char('s'), || {
+            if refuse_watch_only() {
+                return Continue;
+            }
+            Input(Box::new(|input| {
+                let amount: Amount = match input.parse() {
+                    Ok(a) => a,
+                    Err(err) => return report(Err(ContextError::from(err))),
+                };
+                report(
+                    context
+                        .purchase_weth(selected_account(), amount)
+                        .wait()
+                        .map(|outcome| outcome.to_string()),
+                );
+            }))
+        })
+        .with_action(Key::Char('d'), || {
+            if refuse_watch_only() {
+                return Continue;
+            }
+            Input(Box::new(|input| {
+                let amount: Amount = match input.parse() {
+                    Ok(a) => a,
+                    Err(err) => return report(Err(ContextError::from(err))),
+                };
+                report(
+                    context
+                        .magic_weth(selected_account(), amount)
+                        .wait()
+                        .map(|outcome| outcome.to_string()),
+                );
+            }))
+        })
+        .with_action(Key::Char('f'), || {
+            if refuse_watch_only() {
+                return Continue;
+            }
+            Input(Box::new(|input| {
+                let amount: Amount = match input.parse() {
+                    Ok(a) => a,
+                    Err(err) => return report(Err(ContextError::from(err))),
+                };
+                report(
+                    context
+                        .fund(selected_account(), amount)
+                        .wait()
+                        .map(|outcome| outcome.to_string()),
+                );
+                refresh_leaderboard();
+            }))
+        })
         .with_action(Key::Char('c'), || {
-            let account = wallet.accounts().nth(*account_selection.borrow()).unwrap();
-            let _ = context.claim(account).wait();
+            if !refuse_watch_only() {
+                report(
+                    context
+                        .claim(selected_account())
+                        .wait()
+                        .map(|outcome| outcome.to_string()),
+                );
+            }
             Continue
         })
+        .with_tick(Duration::from_millis(250), poll_activity)
         .run(|mut f| {
             let size = f.size();
+            let mut constraints = vec![Constraint::Length(8), Constraint::Min(0), Constraint::Length(8), Constraint::Length(3), Constraint::Length(4)];
+            if !warnings.is_empty() {
+                constraints.insert(0, Constraint::Length(2 * warnings.len() as u16 + 2));
+            }
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
-                .constraints(&[Constraint::Length(8), Constraint::Min(0), Constraint::Length(4)][..])
+                .constraints(&constraints[..])
                 .split(size);
+            let chunks = if warnings.is_empty() {
+                &chunks[..]
+            } else {
+                let warning = Style::default().fg(Color::Red).modifier(Modifier::BOLD);
+                let lines: Vec<_> = warnings
+                    .iter()
+                    .map(|text| Text::styled(format!("{}\n", text), warning))
+                    .collect();
+                Paragraph::new(lines.iter())
+                    .wrap(true)
+                    .block(Block::default().title("Warning").title_style(warning).border_style(warning).borders(Borders::ALL))
+                    .render(&mut f, chunks[0]);
+                &chunks[1..]
+            };
 
             Paragraph::new([
                     Text::raw("\nOnce in a lifetime chance to get rich!\n"),
@@ -165,8 +551,10 @@ fn main() {
 
             let accounts: Vec<_> = wallet.accounts()
                 .map(|account| {
-                    let (eth, weth, contrib, scm) = context.balances(account).wait().unwrap_or((-1.0, -1.0, -1.0, -1.0));
-                    format!("{:?} {:7.2} ETH | {:6.2}>{:6.2} WETH | {:7.2} SCM", account, eth, weth, contrib, scm)
+                    match context.balances(account.address()).wait() {
+                        Ok((eth, weth, contrib, scm)) => format!("[{:>8}] {:?} {:7.2} ETH | {:6.2}>{:6.2} WETH | {:7.2} SCM", account.source(), account.address(), eth, weth, contrib, scm),
+                        Err(_) => format!("[{:>8}] {:?} ???", account.source(), account.address()),
+                    }
                 })
                 .collect();
             SelectableList::default()
@@ -181,13 +569,48 @@ fn main() {
                 .block(Block::default().title("Accounts").borders(Borders::ALL))
                 .render(&mut f, chunks[1]);
 
+            if *show_leaderboard.borrow() {
+                let mut cached = leaderboard.borrow_mut();
+                if cached.is_none() {
+                    *cached = context.contributors().wait().ok().map(|contributors| {
+                        contributors
+                            .iter()
+                            .enumerate()
+                            .map(|(i, contributor)| format!("{:>3}. {:?} {:7.2} WETH | {:6.2}% | {:8.2} SCM\n", i + 1, contributor.address, contributor.contribution, contributor.share(), contributor.projected_scm()))
+                            .collect::<Vec<_>>()
+                    });
+                }
+                let rows = match &*cached {
+                    Some(lines) => lines.iter().map(|line| Text::raw(line.as_str())).collect(),
+                    None => vec![Text::raw("???")],
+                };
+                Paragraph::new(rows.iter())
+                    .block(Block::default().title(&format!("Leaderboard (target {} WETH)", ICO_TARGET)).borders(Borders::ALL))
+                    .render(&mut f, chunks[2]);
+            } else {
+                let feed = feed.borrow();
+                let activity = feed
+                    .iter()
+                    .map(|event| Text::raw(format!("{}\n", event)))
+                    .collect::<Vec<_>>();
+                Paragraph::new(activity.iter())
+                    .block(Block::default().title("Activity").borders(Borders::ALL))
+                    .render(&mut f, chunks[2]);
+            }
+
+            Paragraph::new([Text::raw(status.borrow().as_str())].iter())
+                .block(Block::default().title("Status").borders(Borders::ALL))
+                .render(&mut f, chunks[3]);
+
             Paragraph::new([
                     Text::styled("q", Style::default().modifier(Modifier::BOLD)),
                     Text::raw(": Quit                   "),
                     Text::styled("F5", Style::default().modifier(Modifier::BOLD)),
                     Text::raw(": Refresh View          "),
                     Text::styled("^/v", Style::default().modifier(Modifier::BOLD)),
-                    Text::raw(": Select Account\n"),
+                    Text::raw(": Select Account        "),
+                    Text::styled("l", Style::default().modifier(Modifier::BOLD)),
+                    Text::raw(": Activity/Leaderboard\n"),
                     Text::styled("s", Style::default().modifier(Modifier::BOLD)),
                     Text::raw(": Purchase WETH          "),
                     Text::styled("d", Style::default().modifier(Modifier::BOLD)),
@@ -200,7 +623,91 @@ fn main() {
                 .wrap(true)
                 .alignment(Alignment::Left)
                 .block(Block::default().title("Help").borders(Borders::ALL))
-                .render(&mut f, chunks[2]);
+                .render(&mut f, chunks[4]);
+        })?;
+
+    Ok(())
+}
+
+/// Subscribes to the ICO activity and streams it on a separate thread. The
+/// returned receiver is disconnected once subscribing or polling fails.
+fn watch_activity<F>(subscribe: F) -> Receiver<ActivityEvent>
+where
+    F: Future<Item = Activity<Http>, Error = ContextError> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let activity = match subscribe.wait() {
+            Ok(activity) => activity,
+            Err(_) => return,
+        };
+        for event in activity.stream(ACTIVITY_POLL_INTERVAL).wait() {
+            match event {
+                Ok(event) if sender.send(event).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Loads the wallet from the configured account sources. Mnemonic accounts
+/// are discovered with the context's contracts if they are available.
+fn load_wallet(
+    opt: &Opt,
+    web3: &Web3<Http>,
+    context: Option<&Context<Http>>,
+) -> Result<Wallet, ContextError> {
+    let private_keys: Vec<_> = opt
+        .private_key_file
+        .iter()
+        .cloned()
+        .map(PrivateKeySource::File)
+        .chain(
+            opt.private_key_env
+                .iter()
+                .cloned()
+                .map(PrivateKeySource::Env),
+        )
+        .chain(if opt.private_key_prompt {
+            Some(PrivateKeySource::Prompt)
+        } else {
+            None
         })
-        .unwrap();
+        .collect();
+
+    let mut wallet = Wallet::default();
+    if !private_keys.is_empty() {
+        wallet.merge(Wallet::with_private_keys(private_keys, || {
+            gui::prompt_password("Private key: ")
+        })?);
+    }
+    if !opt.keystore.is_empty() {
+        wallet.merge(Wallet::with_keystores(&opt.keystore, |path| {
+            gui::prompt_password(&format!("Password for {}: ", path.display()))
+        })?);
+    }
+    if let Some(mnemonic) = &opt.mnemonic {
+        let keychain = Keychain::new(
+            &mnemonic.as_inner(),
+            &opt.passphrase,
+            opt.derivation_path.clone(),
+        )?;
+        wallet.merge(if opt.discover {
+            Wallet::discover(keychain, opt.gap_limit, |account| match context {
+                Some(context) => Either::A(context.is_used(account).map_err(WalletError::from)),
+                None => Either::B(context::has_activity(web3, account).map_err(WalletError::from)),
+            })
+            .wait()?
+        } else {
+            Wallet::with_mnemonic(&keychain, opt.accounts)?
+        });
+    }
+    if opt.node_accounts || wallet.is_empty() {
+        wallet.merge(Wallet::local(web3.clone()).wait()?);
+    }
+    wallet.merge(Wallet::watch_only(opt.watch.iter().cloned()));
+
+    Ok(wallet)
 }
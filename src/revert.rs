@@ -0,0 +1,284 @@
+use ethabi::ParamType;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+use std::fmt::{self, Display, Formatter};
+use web3::api::Eth;
+use web3::error::Error as Web3Error;
+use web3::futures::Future;
+use web3::types::{BlockNumber, CallRequest, TransactionId, H256, U256};
+use web3::Transport;
+
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// The revert error messages of geth and Ganache, along with the separator
+/// that precedes the revert reason if there is one.
+const REVERT_MESSAGES: [(&str, &str); 2] = [
+    ("execution reverted", ": "),
+    ("VM Exception while processing transaction: revert", " "),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RevertReason {
+    /// A `revert(string)` or `require(bool, string)` message.
+    Message(String),
+    /// A Solidity `Panic(uint256)` error code.
+    Panic(U256),
+    /// Revert data that isn't a standard error, or no data at all.
+    Unknown(Vec<u8>),
+}
+
+impl RevertReason {
+    /// Decodes ABI encoded revert data.
+    pub fn decode(data: &[u8]) -> RevertReason {
+        let (selector, params) = if data.len() >= 4 {
+            data.split_at(4)
+        } else {
+            return RevertReason::Unknown(data.to_vec());
+        };
+
+        let decoded = if selector == ERROR_SELECTOR {
+            ethabi::decode(&[ParamType::String], params)
+                .ok()
+                .and_then(|tokens| tokens.into_iter().next()?.to_string())
+                .map(RevertReason::Message)
+        } else if selector == PANIC_SELECTOR {
+            ethabi::decode(&[ParamType::Uint(256)], params)
+                .ok()
+                .and_then(|tokens| tokens.into_iter().next()?.to_uint())
+                .map(RevertReason::Panic)
+        } else {
+            None
+        };
+
+        decoded.unwrap_or_else(|| RevertReason::Unknown(data.to_vec()))
+    }
+
+    /// Extracts a revert reason from a node error. Nodes report reverts in
+    /// different ways: geth includes the revert data as a hex string in the
+    /// error data, Ganache includes an object with the return data per
+    /// transaction, and both also include the reason in the message. The error
+    /// data is preferred since the message may be truncated or ambiguous.
+    pub fn from_web3_error(err: &Web3Error) -> Option<RevertReason> {
+        let err = match err {
+            Web3Error::Rpc(err) => err,
+            _ => return None,
+        };
+
+        if let Some(reason) = err.data.as_ref().and_then(revert_data) {
+            return Some(reason);
+        }
+
+        Some(match revert_message(&err.message)?.trim() {
+            "" => RevertReason::Unknown(Vec::new()),
+            reason => RevertReason::Message(reason.to_string()),
+        })
+    }
+}
+
+impl Display for RevertReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RevertReason::Message(message) => write!(f, "\"{}\"", message),
+            RevertReason::Panic(code) => {
+                write!(f, "panic 0x{:02x} ({})", code, panic_description(*code))
+            }
+            RevertReason::Unknown(data) if data.is_empty() => f.write_str("no reason given"),
+            RevertReason::Unknown(data) => write!(f, "unknown revert data 0x{}", hex(data)),
+        }
+    }
+}
+
+/// Re-executes a mined transaction with `eth_call` at the block it was
+/// included in to recover its revert reason. Since a reverted transaction does
+/// not modify state, executing it on top of its block yields the same result.
+pub fn replay<T>(
+    eth: Eth<T>,
+    hash: H256,
+    block: u64,
+) -> impl Future<Item = RevertReason, Error = Web3Error>
+where
+    T: Transport,
+{
+    eth.transaction(TransactionId::Hash(hash))
+        .and_then(move |tx| {
+            let tx = tx.ok_or_else(|| {
+                Web3Error::InvalidResponse(format!("transaction {:?} not found", hash))
+            })?;
+            Ok(CallRequest {
+                from: Some(tx.from),
+                to: tx.to.unwrap_or_default(),
+                gas: Some(tx.gas),
+                gas_price: Some(tx.gas_price),
+                value: Some(tx.value),
+                data: Some(tx.input),
+            })
+        })
+        .and_then(move |call| {
+            eth.call(call, Some(BlockNumber::Number(block.into())))
+                .then(|result| match result {
+                    Ok(data) => Ok(RevertReason::decode(&data.0)),
+                    Err(err) => RevertReason::from_web3_error(&err).ok_or(err),
+                })
+        })
+}
+
+/// Returns the revert reason of a revert error message, which is empty if the
+/// transaction reverted without a reason, or `None` for other errors.
+fn revert_message(message: &str) -> Option<&str> {
+    REVERT_MESSAGES.iter().find_map(|&(prefix, separator)| {
+        if message == prefix {
+            Some("")
+        } else if message.starts_with(prefix) && message[prefix.len()..].starts_with(separator) {
+            Some(&message[prefix.len() + separator.len()..])
+        } else {
+            None
+        }
+    })
+}
+
+fn revert_data(data: &Value) -> Option<RevertReason> {
+    match data {
+        Value::String(hex) => parse_hex(hex).map(|data| RevertReason::decode(&data)),
+        Value::Object(fields) => {
+            if let Some(reason) = fields.get("reason").and_then(Value::as_str) {
+                return Some(RevertReason::Message(reason.to_string()));
+            }
+            if let Some(hex) = fields.get("return").and_then(Value::as_str) {
+                return parse_hex(hex).map(|data| RevertReason::decode(&data));
+            }
+            fields.values().filter_map(revert_data).next()
+        }
+        _ => None,
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim_start_matches("0x");
+    if hex.is_empty() {
+        return None;
+    }
+    hex.from_hex().ok()
+}
+
+fn hex(data: &[u8]) -> String {
+    data.to_hex()
+}
+
+fn panic_description(code: U256) -> &'static str {
+    if code > U256::from(0xff) {
+        return "unknown panic code";
+    }
+    match code.low_u32() {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethabi::Token;
+    use serde_json::json;
+
+    fn rpc_error(message: &str, data: Option<Value>) -> Web3Error {
+        let error = json!({ "code": 3, "message": message, "data": data });
+        Web3Error::Rpc(serde_json::from_value(error).unwrap())
+    }
+
+    fn error_data(message: &str) -> Vec<u8> {
+        let mut data = ERROR_SELECTOR.to_vec();
+        data.extend(ethabi::encode(&[Token::String(message.to_string())]));
+        data
+    }
+
+    #[test]
+    fn decode_revert_data() {
+        assert_eq!(
+            RevertReason::decode(&error_data("ICO is closed")),
+            RevertReason::Message("ICO is closed".to_string())
+        );
+
+        let mut panic = PANIC_SELECTOR.to_vec();
+        panic.extend(ethabi::encode(&[Token::Uint(0x11.into())]));
+        assert_eq!(
+            RevertReason::decode(&panic),
+            RevertReason::Panic(0x11.into())
+        );
+
+        assert_eq!(
+            RevertReason::decode(&[0xde, 0xad]),
+            RevertReason::Unknown(vec![0xde, 0xad])
+        );
+    }
+
+    #[test]
+    fn revert_reason_from_message() {
+        let reason = |message| RevertReason::from_web3_error(&rpc_error(message, None));
+
+        assert_eq!(
+            reason("execution reverted: not enough WETH"),
+            Some(RevertReason::Message("not enough WETH".to_string()))
+        );
+        assert_eq!(
+            reason("execution reverted"),
+            Some(RevertReason::Unknown(Vec::new()))
+        );
+        assert_eq!(
+            reason("VM Exception while processing transaction: revert ICO is closed"),
+            Some(RevertReason::Message("ICO is closed".to_string()))
+        );
+        assert_eq!(
+            reason("VM Exception while processing transaction: revert"),
+            Some(RevertReason::Unknown(Vec::new()))
+        );
+
+        // errors that merely mention a revert are not reverts
+        assert_eq!(reason("transaction would be reverted"), None);
+        assert_eq!(reason("execution reverted by the node"), None);
+        assert_eq!(
+            reason("VM Exception while processing transaction: reverted"),
+            None
+        );
+        assert_eq!(
+            reason("VM Exception while processing transaction: out of gas"),
+            None
+        );
+    }
+
+    #[test]
+    fn revert_reason_prefers_error_data() {
+        let data = format!("0x{}", hex(&error_data("from data")));
+        assert_eq!(
+            RevertReason::from_web3_error(&rpc_error(
+                "execution reverted: from message",
+                Some(Value::String(data))
+            )),
+            Some(RevertReason::Message("from data".to_string()))
+        );
+
+        // Ganache reports the return data per transaction hash
+        let data = json!({
+            "0x5c4c4d2f4b8b1a2e41d1f3a0e7a4a3b1b5e7c6d9f0a1b2c3d4e5f60718293a4b": {
+                "error": "revert",
+                "return": format!("0x{}", hex(&error_data("from return data"))),
+            }
+        });
+        assert_eq!(
+            RevertReason::from_web3_error(&rpc_error(
+                "VM Exception while processing transaction: revert",
+                Some(data)
+            )),
+            Some(RevertReason::Message("from return data".to_string()))
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! The scrypt key derivation function (RFC 7914) used by Web3 Secret Storage
+//! key files.
+//!
+//! The `scrypt` crate rejects parameters where `N >= 2^(16 * r)`, but geth
+//! happily produces and accepts key files with such parameters (including the
+//! Web3 Secret Storage test vector, with `n = 262144` and `r = 1`), so the KDF
+//! is implemented here on top of PBKDF2-HMAC-SHA256.
+
+use hmac::Hmac;
+use sha2::Sha256;
+
+/// Derives a key from a password filling the output buffer. The cost
+/// parameter `n` must be a power of two and `r` and `p` must be non-zero.
+pub fn scrypt(password: &[u8], salt: &[u8], n: usize, r: usize, p: usize, output: &mut [u8]) {
+    let block_len = 128 * r;
+    let mut blocks = vec![0; p * block_len];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, 1, &mut blocks);
+
+    let mut scratch = vec![0; n * block_len];
+    for block in blocks.chunks_mut(block_len) {
+        romix(block, &mut scratch, n);
+    }
+
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password, &blocks, 1, output);
+}
+
+fn romix(block: &mut [u8], scratch: &mut [u8], n: usize) {
+    let len = block.len();
+    let mut x = block.to_vec();
+    let mut t = vec![0; len];
+
+    for i in 0..n {
+        scratch[i * len..(i + 1) * len].copy_from_slice(&x);
+        block_mix(&x, &mut t);
+        x.copy_from_slice(&t);
+    }
+    for _ in 0..n {
+        let j = integerify(&x) % n;
+        for (x, v) in x.iter_mut().zip(&scratch[j * len..(j + 1) * len]) {
+            *x ^= v;
+        }
+        block_mix(&x, &mut t);
+        x.copy_from_slice(&t);
+    }
+
+    block.copy_from_slice(&x);
+}
+
+fn integerify(block: &[u8]) -> usize {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&block[block.len() - 64..block.len() - 56]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+fn block_mix(input: &[u8], output: &mut [u8]) {
+    let half = input.len() / 128;
+    let mut x = [0; 64];
+    x.copy_from_slice(&input[input.len() - 64..]);
+
+    for (i, chunk) in input.chunks(64).enumerate() {
+        for (x, c) in x.iter_mut().zip(chunk) {
+            *x ^= c;
+        }
+        salsa20_8(&mut x);
+
+        // even blocks go to the first half of the output, odd to the second
+        let offset = (i / 2 + (i % 2) * half) * 64;
+        output[offset..offset + 64].copy_from_slice(&x);
+    }
+}
+
+fn salsa20_8(block: &mut [u8; 64]) {
+    let mut input = [0u32; 16];
+    for (word, bytes) in input.iter_mut().zip(block.chunks(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+
+    let mut x = input;
+    for _ in 0..4 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 5, 9, 13, 1);
+        quarter_round(&mut x, 10, 14, 2, 6);
+        quarter_round(&mut x, 15, 3, 7, 11);
+        quarter_round(&mut x, 0, 1, 2, 3);
+        quarter_round(&mut x, 5, 6, 7, 4);
+        quarter_round(&mut x, 10, 11, 8, 9);
+        quarter_round(&mut x, 15, 12, 13, 14);
+    }
+
+    for (i, bytes) in block.chunks_mut(4).enumerate() {
+        bytes.copy_from_slice(&x[i].wrapping_add(input[i]).to_le_bytes());
+    }
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+    x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+    x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+    x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hex::ToHex;
+
+    fn check(password: &str, salt: &str, n: usize, r: usize, p: usize, expected: &str) {
+        let mut output = [0; 64];
+        scrypt(password.as_bytes(), salt.as_bytes(), n, r, p, &mut output);
+        assert_eq!(output.to_hex::<String>(), expected);
+    }
+
+    #[test]
+    fn rfc7914_test_vectors() {
+        check(
+            "",
+            "",
+            16,
+            1,
+            1,
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906",
+        );
+        check(
+            "password",
+            "NaCl",
+            1024,
+            8,
+            16,
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b3731622eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640",
+        );
+    }
+}
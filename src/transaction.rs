@@ -0,0 +1,238 @@
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio_timer::Timer;
+use web3::api::{Eth, Namespace};
+use web3::error::Error as Web3Error;
+use web3::futures::future::{self, Either, Loop};
+use web3::futures::Future;
+use web3::helpers::{self, CallFuture};
+use web3::types::{Address, TransactionId, TransactionReceipt, H256, U256};
+use web3::Transport;
+
+/// How long to wait for a transaction to be mined and confirmed before giving
+/// up by default, so that a transaction that is never mined, for example
+/// because its gas price is too low, doesn't block forever.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// The interval at which the transaction receipt is polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A handle to a sent transaction that can be used to wait for it to be mined
+/// and confirmed.
+pub struct PendingTransaction<T: Transport> {
+    eth: Eth<T>,
+    hash: H256,
+    confirmations: usize,
+    timeout: Duration,
+}
+
+impl<T: Transport> PendingTransaction<T> {
+    pub fn new(eth: Eth<T>, hash: H256) -> PendingTransaction<T> {
+        PendingTransaction {
+            eth,
+            hash,
+            confirmations: 0,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the number of blocks that need to be mined on top of the block
+    /// including the transaction before it is considered confirmed.
+    pub fn confirmations(mut self, confirmations: usize) -> PendingTransaction<T> {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Sets how long to wait for the transaction to be mined and confirmed
+    /// before giving up.
+    pub fn timeout(mut self, timeout: Duration) -> PendingTransaction<T> {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Polls for the transaction receipt until the transaction is mined and has
+    /// the required number of confirmations, or fails once the timeout has
+    /// elapsed. The receipt is re-fetched on every poll so that a reorg that
+    /// drops the transaction is handled.
+    pub fn wait(self) -> impl Future<Item = TransactionOutcome, Error = WaitError> {
+        let PendingTransaction {
+            eth,
+            hash,
+            confirmations,
+            timeout,
+        } = self;
+        let timer = Timer::default();
+        let deadline = Instant::now() + timeout;
+
+        future::loop_fn((), move |()| {
+            use Either::*;
+
+            let eth = eth.clone();
+            let timer = timer.clone();
+            receipt(&eth, hash)
+                .join(eth.block_number())
+                .map_err(WaitError::from)
+                .and_then(move |(receipt, current_block)| {
+                    let receipt = receipt
+                        .filter(|receipt| receipt.receipt.block_number.is_some())
+                        .filter(|receipt| {
+                            let mined = receipt.receipt.block_number.unwrap_or_default().as_u64();
+                            current_block.as_u64() >= mined + confirmations as u64
+                        });
+
+                    match receipt {
+                        Some(Receipt {
+                            receipt,
+                            effective_gas_price: Some(gas_price),
+                        }) => A(A(future::ok(Loop::Break(TransactionOutcome::new(
+                            receipt, gas_price,
+                        ))))),
+                        // receipts from nodes that predate EIP-1559 don't
+                        // include the effective gas price, which is always
+                        // the transaction's gas price for those
+                        Some(Receipt { receipt, .. }) => A(B(eth
+                            .transaction(TransactionId::Hash(hash))
+                            .map(move |tx| {
+                                let gas_price = tx.map(|tx| tx.gas_price).unwrap_or_default();
+                                Loop::Break(TransactionOutcome::new(receipt, gas_price))
+                            })
+                            .map_err(WaitError::from))),
+                        None if Instant::now() >= deadline => {
+                            B(A(future::err(WaitError::Timeout { hash, timeout })))
+                        }
+                        None => B(B(timer
+                            .sleep(POLL_INTERVAL)
+                            .map(Loop::Continue)
+                            .map_err(|err| Web3Error::Transport(err.to_string()).into()))),
+                    }
+                })
+        })
+    }
+}
+
+/// A transaction receipt along with the effective gas price paid, which the
+/// receipt type of the web3 crate doesn't have.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Receipt {
+    #[serde(flatten)]
+    receipt: TransactionReceipt,
+    #[serde(default)]
+    effective_gas_price: Option<U256>,
+}
+
+fn receipt<T>(eth: &Eth<T>, hash: H256) -> CallFuture<Option<Receipt>, T::Out>
+where
+    T: Transport,
+{
+    CallFuture::new(
+        eth.transport()
+            .execute("eth_getTransactionReceipt", vec![helpers::serialize(&hash)]),
+    )
+}
+
+#[derive(Debug, Error)]
+pub enum WaitError {
+    #[error("web3 error: {0}")]
+    Web3(#[from] Web3Error),
+
+    #[error(
+        "transaction {hash:?} was not mined and confirmed within {} seconds",
+        .timeout.as_secs()
+    )]
+    Timeout { hash: H256, timeout: Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionOutcome {
+    pub hash: H256,
+    pub success: bool,
+    pub block_number: u64,
+    pub gas_used: U256,
+    pub effective_gas_price: U256,
+    /// The address of the created contract for contract creation transactions.
+    pub contract_address: Option<Address>,
+}
+
+impl TransactionOutcome {
+    fn new(receipt: TransactionReceipt, gas_price: U256) -> TransactionOutcome {
+        TransactionOutcome {
+            hash: receipt.transaction_hash,
+            // pre-Byzantium receipts don't have a status, so assume success
+            success: receipt
+                .status
+                .map(|status| status.as_u64() == 1)
+                .unwrap_or(true),
+            block_number: receipt.block_number.unwrap_or_default().as_u64(),
+            gas_used: receipt.gas_used.unwrap_or_default(),
+            effective_gas_price: gas_price,
+            contract_address: receipt.contract_address,
+        }
+    }
+}
+
+impl Display for TransactionOutcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "transaction {:?} {} in block {} using {} gas at {} wei",
+            self.hash,
+            if self.success {
+                "succeeded"
+            } else {
+                "reverted"
+            },
+            self.block_number,
+            self.gas_used,
+            self.effective_gas_price,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn receipt_json() -> serde_json::Value {
+        json!({
+            "transactionHash": format!("0x{:064x}", 1),
+            "transactionIndex": "0x0",
+            "blockHash": format!("0x{:064x}", 2),
+            "blockNumber": "0x2a",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": "0x2222222222222222222222222222222222222222",
+            "cumulativeGasUsed": "0x5208",
+            "gasUsed": "0x5208",
+            "contractAddress": null,
+            "logs": [],
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "status": "0x1",
+            "type": "0x2",
+        })
+    }
+
+    #[test]
+    fn receipt_with_effective_gas_price() {
+        let mut json = receipt_json();
+        json["effectiveGasPrice"] = "0x3b9aca07".into();
+        let receipt: Receipt = serde_json::from_value(json).unwrap();
+        assert_eq!(receipt.effective_gas_price, Some(1_000_000_007.into()));
+
+        let outcome = TransactionOutcome::new(receipt.receipt, 1_000_000_007.into());
+        assert_eq!(outcome.hash, H256::from_low_u64_be(1));
+        assert!(outcome.success);
+        assert_eq!(outcome.block_number, 42);
+        assert_eq!(outcome.gas_used, 21000.into());
+        assert_eq!(outcome.effective_gas_price, 1_000_000_007.into());
+    }
+
+    #[test]
+    fn receipt_without_effective_gas_price() {
+        let receipt: Receipt = serde_json::from_value(receipt_json()).unwrap();
+        assert_eq!(receipt.effective_gas_price, None);
+        assert_eq!(receipt.receipt.block_number, Some(42.into()));
+    }
+}
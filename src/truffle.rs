@@ -1,4 +1,5 @@
 use ethabi::Contract;
+use rustc_hex::{FromHex, ToHex};
 use serde::Deserialize;
 use serde_json::Error as JsonError;
 use std::collections::HashMap;
@@ -6,11 +7,36 @@ use std::fs::File;
 use std::io::Error as IoError;
 use std::path::Path;
 use thiserror::Error;
-use web3::types::Address;
+use web3::types::{Address, H256};
 
+/// A truffle contract artifact as written to `build/contracts`.
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Artifact {
+    #[serde(default)]
+    pub contract_name: String,
     pub abi: Contract,
+    /// The hex encoded contract creation code.
+    #[serde(default)]
+    pub bytecode: String,
+    /// The hex encoded runtime code of the deployed contract.
+    #[serde(default)]
+    pub deployed_bytecode: String,
+    // the source maps, compiler and update time are not needed by the client,
+    // they are only kept to model the complete artifact format
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub source_map: String,
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub deployed_source_map: String,
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub compiler: Option<Compiler>,
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub updated_at: Option<String>,
+    #[serde(default)]
     pub networks: HashMap<String, Network>,
 }
 
@@ -31,11 +57,123 @@ impl Artifact {
 
         Ok(artifact)
     }
+
+    /// Decodes the contract creation bytecode. Bytecode that still contains
+    /// library link placeholders can not be deployed.
+    pub fn decode_bytecode(&self) -> Result<Vec<u8>, ArtifactError> {
+        decode_bytecode(&self.bytecode)
+    }
+
+    /// Decodes the runtime bytecode of the deployed contract. Deployed code is
+    /// compared in its hex encoding by `matches_code`, so the client doesn't
+    /// need this itself.
+    #[allow(dead_code)]
+    pub fn decode_deployed_bytecode(&self) -> Result<Vec<u8>, ArtifactError> {
+        decode_bytecode(&self.deployed_bytecode)
+    }
+
+    /// Checks whether code retrieved with `eth_getCode` matches the artifact's
+    /// deployed bytecode. The compiler metadata hash appended to the code is
+    /// ignored, since it changes with the source file paths and comments, as
+    /// well as library link placeholders that are replaced on deployment.
+    pub fn matches_code(&self, code: &[u8]) -> Result<bool, ArtifactError> {
+        let expected = self.deployed_bytecode.trim_start_matches("0x");
+        if expected.is_empty() {
+            return Err(ArtifactError::MissingBytecode);
+        }
+        if !expected.is_ascii() || expected.len() % 2 != 0 {
+            return Err(ArtifactError::InvalidBytecode);
+        }
+
+        let actual: String = code.to_hex();
+        let expected = strip_metadata(expected).as_bytes();
+        let actual = strip_metadata(&actual).as_bytes();
+        if expected.len() != actual.len() {
+            return Ok(false);
+        }
+
+        let mut i = 0;
+        while i + 2 <= expected.len() {
+            if expected[i..].starts_with(b"__") {
+                i += LINK_PLACEHOLDER_LEN;
+                continue;
+            }
+            if !expected[i..i + 2].eq_ignore_ascii_case(&actual[i..i + 2]) {
+                return Ok(false);
+            }
+            i += 2;
+        }
+
+        Ok(true)
+    }
 }
 
+/// The length of a library link placeholder in hex encoded bytecode, which
+/// takes the place of a 20 byte address.
+const LINK_PLACEHOLDER_LEN: usize = 40;
+
+/// Strips the CBOR encoded compiler metadata from hex encoded bytecode. The
+/// metadata is appended to the code followed by its length as a 16-bit big
+/// endian integer.
+fn strip_metadata(code: &str) -> &str {
+    if code.len() < 4 {
+        return code;
+    }
+
+    let len = match code[code.len() - 4..].from_hex::<Vec<u8>>() {
+        Ok(len) => (len[0] as usize) << 8 | len[1] as usize,
+        Err(_) => return code,
+    };
+    let metadata_len = (len + 2) * 2;
+    if metadata_len > code.len() {
+        return code;
+    }
+
+    // the metadata is a CBOR map, so make sure that is what we are stripping
+    let start = code.len() - metadata_len;
+    match code[start..start + 2].from_hex::<Vec<u8>>() {
+        Ok(map) if map[0] & 0xe0 == 0xa0 => &code[..start],
+        _ => code,
+    }
+}
+
+fn decode_bytecode(bytecode: &str) -> Result<Vec<u8>, ArtifactError> {
+    let bytecode = bytecode.trim_start_matches("0x");
+    if bytecode.is_empty() {
+        return Err(ArtifactError::MissingBytecode);
+    }
+    if bytecode.contains("__") {
+        return Err(ArtifactError::UnlinkedBytecode);
+    }
+
+    bytecode
+        .from_hex()
+        .map_err(|_| ArtifactError::InvalidBytecode)
+}
+
+/// The compiler an artifact was built with, which is not used by the client.
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
+pub struct Compiler {
+    pub name: String,
+    pub version: String,
+}
+
+/// A deployment of the contract on a network.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Network {
     pub address: Address,
+    /// The hash of the transaction that deployed the contract. Only the
+    /// address is needed by the client.
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub transaction_hash: Option<H256>,
+    /// The addresses of the libraries the deployed contract was linked
+    /// against by library name. The client doesn't deploy linked contracts.
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub links: HashMap<String, Address>,
 }
 
 #[derive(Debug, Error)]
@@ -45,4 +183,201 @@ pub enum ArtifactError {
 
     #[error("failed to parse contract artifact JSON")]
     Json(#[from] JsonError),
+
+    #[error("contract artifact for {0} not found")]
+    NotFound(String),
+
+    #[error("contract artifact has no bytecode")]
+    MissingBytecode,
+
+    #[error("contract artifact bytecode has unlinked libraries")]
+    UnlinkedBytecode,
+
+    #[error("contract artifact bytecode is not valid hex")]
+    InvalidBytecode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "6080604052348015600f57600080fd5b50";
+
+    fn metadata(hash: u8) -> String {
+        format!(
+            "a165627a7a72305820{}0029",
+            format!("{:02x}", hash).repeat(32)
+        )
+    }
+
+    fn artifact(deployed_bytecode: &str) -> Artifact {
+        serde_json::from_value(serde_json::json!({
+            "abi": [],
+            "deployedBytecode": deployed_bytecode,
+        }))
+        .unwrap()
+    }
+
+    fn code(hex: &str) -> Vec<u8> {
+        hex.from_hex().unwrap()
+    }
+
+    #[test]
+    fn matches_identical_code() {
+        let deployed = format!("{}{}", CODE, metadata(1));
+        let artifact = artifact(&format!("0x{}", deployed.to_uppercase()));
+        assert!(artifact.matches_code(&code(&deployed)).unwrap());
+    }
+
+    #[test]
+    fn ignores_metadata() {
+        let artifact = artifact(&format!("0x{}{}", CODE, metadata(1)));
+        assert!(artifact
+            .matches_code(&code(&format!("{}{}", CODE, metadata(2))))
+            .unwrap());
+
+        // code without metadata is compared in full
+        let artifact = self::artifact(&format!("0x{}", CODE));
+        assert!(artifact.matches_code(&code(CODE)).unwrap());
+    }
+
+    #[test]
+    fn ignores_link_placeholders() {
+        let placeholder = format!("__Library{}", "_".repeat(31));
+        assert_eq!(placeholder.len(), LINK_PLACEHOLDER_LEN);
+        let artifact = artifact(&format!("0x73{}{}{}", placeholder, CODE, metadata(1)));
+
+        let linked = format!("73{}{}{}", "42".repeat(20), CODE, metadata(2));
+        assert!(artifact.matches_code(&code(&linked)).unwrap());
+    }
+
+    #[test]
+    fn detects_mismatches() {
+        let artifact = artifact(&format!("0x{}{}", CODE, metadata(1)));
+
+        let mut different = CODE.to_string();
+        different.replace_range(..2, "61");
+        for actual in &[
+            format!("{}{}", different, metadata(1)),
+            format!("{}00{}", CODE, metadata(1)),
+            String::new(),
+        ] {
+            assert!(!artifact.matches_code(&code(actual)).unwrap(), "{}", actual);
+        }
+    }
+
+    #[test]
+    fn requires_deployed_bytecode() {
+        for deployed in &["", "0x"] {
+            match artifact(deployed).matches_code(&code(CODE)) {
+                Err(ArtifactError::MissingBytecode) => {}
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+        match artifact("0x608").matches_code(&code(CODE)) {
+            Err(ArtifactError::InvalidBytecode) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn deserialize_truffle_artifact() {
+        // a truffle 5 artifact with the sources and AST left out for brevity
+        let artifact: Artifact = serde_json::from_str(
+            r#"{
+  "contractName": "Migrations",
+  "abi": [
+    {
+      "constant": true,
+      "inputs": [],
+      "name": "last_completed_migration",
+      "outputs": [{ "internalType": "uint256", "name": "", "type": "uint256" }],
+      "payable": false,
+      "stateMutability": "view",
+      "type": "function"
+    },
+    {
+      "constant": false,
+      "inputs": [{ "internalType": "uint256", "name": "completed", "type": "uint256" }],
+      "name": "setCompleted",
+      "outputs": [],
+      "payable": false,
+      "stateMutability": "nonpayable",
+      "type": "function"
+    }
+  ],
+  "metadata": "{\"compiler\":{\"version\":\"0.5.12+commit.7709ece9\"},\"language\":\"Solidity\"}",
+  "bytecode": "0x6080604052348015600f57600080fd5b50a165627a7a7230582001010101010101010101010101010101010101010101010101010101010101010029",
+  "deployedBytecode": "0x6080604052348015600f57600080fd5b50a165627a7a7230582001010101010101010101010101010101010101010101010101010101010101010029",
+  "sourceMap": "34:480:0:-;;;123:50;8:9:-1;5:2;;;30:1;27;20:12;5:2;123:50:0;;;;;;",
+  "deployedSourceMap": "34:480:0:-;;;;8:9:-1;5:2;;;30:1;27;20:12;5:2;34:480:0;;",
+  "source": "pragma solidity >=0.4.21 <0.7.0;\n\ncontract Migrations {}\n",
+  "sourcePath": "/home/user/scam-ico/contracts/Migrations.sol",
+  "ast": { "absolutePath": "/home/user/scam-ico/contracts/Migrations.sol" },
+  "legacyAST": { "absolutePath": "/home/user/scam-ico/contracts/Migrations.sol" },
+  "compiler": {
+    "name": "solc",
+    "version": "0.5.12+commit.7709ece9.Emscripten.clang"
+  },
+  "networks": {
+    "5777": {
+      "events": {},
+      "links": {},
+      "address": "0x254dffcd3277C0b1660F6d42EFbB754edaBAbC2B",
+      "transactionHash": "0x2e2a9cf4b5c5ef2b9dc7a2e0d2d6d3aa6c5a1d8b4b0e6f7c8d9e0a1b2c3d4e5f"
+    }
+  },
+  "schemaVersion": "3.0.16",
+  "updatedAt": "2019-10-20T12:34:56.789Z",
+  "devdoc": { "methods": {} },
+  "userdoc": { "methods": {} }
+}"#,
+        )
+        .unwrap();
+
+        assert_eq!(artifact.contract_name, "Migrations");
+        assert!(artifact.abi.function("setCompleted").is_ok());
+        assert_eq!(
+            artifact.decode_bytecode().unwrap(),
+            code(&format!("{}{}", CODE, metadata(1)))
+        );
+        assert_eq!(
+            artifact.decode_deployed_bytecode().unwrap(),
+            artifact.decode_bytecode().unwrap()
+        );
+        assert!(artifact.source_map.starts_with("34:480:0:-"));
+        assert!(artifact.deployed_source_map.starts_with("34:480:0:-"));
+        let compiler = artifact.compiler.as_ref().unwrap();
+        assert_eq!(compiler.name, "solc");
+        assert_eq!(compiler.version, "0.5.12+commit.7709ece9.Emscripten.clang");
+        assert_eq!(
+            artifact.updated_at.as_ref().map(String::as_str),
+            Some("2019-10-20T12:34:56.789Z")
+        );
+
+        let network = &artifact.networks["5777"];
+        assert_eq!(
+            network.address,
+            Address::from_slice(&code("254dffcd3277c0b1660f6d42efbb754edababc2b"))
+        );
+        assert_eq!(
+            network.transaction_hash,
+            Some(H256::from_slice(&code(
+                "2e2a9cf4b5c5ef2b9dc7a2e0d2d6d3aa6c5a1d8b4b0e6f7c8d9e0a1b2c3d4e5f"
+            )))
+        );
+        assert!(network.links.is_empty());
+    }
+
+    #[test]
+    fn deserialize_bare_artifact() {
+        let artifact = artifact("");
+        assert!(artifact.contract_name.is_empty());
+        assert!(artifact.compiler.is_none());
+        assert!(artifact.networks.is_empty());
+        match artifact.decode_bytecode() {
+            Err(ArtifactError::MissingBytecode) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
 }
@@ -1,28 +1,181 @@
+use crate::bip32::{Bip32Error, ExtendedPrivateKey, PathTemplate};
+use crate::context::ContextError;
+use crate::keystore::{KeyFile, KeystoreError};
 use bip39::{Mnemonic, Seed};
 use ethsign::SecretKey;
+use rustc_hex::FromHex;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
 use web3::error::Error as Web3Error;
+use web3::futures::future::{self, Either, Loop};
 use web3::futures::Future;
 use web3::types::Address;
 use web3::{Transport, Web3};
 
+#[derive(Default)]
 pub struct Wallet {
     accounts: Vec<Account>,
 }
 
-struct Account {
+#[derive(Clone)]
+pub struct Account {
     public: Address,
-    _private: Option<SecretKey>,
+    private: Option<SecretKey>,
+    source: AccountSource,
+}
+
+impl Account {
+    pub fn address(&self) -> Address {
+        self.public
+    }
+
+    pub fn secret(&self) -> Option<&SecretKey> {
+        self.private.as_ref()
+    }
+
+    pub fn source(&self) -> &AccountSource {
+        &self.source
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.source == AccountSource::WatchOnly
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountSource {
+    Node,
+    Mnemonic(u32),
+    Keystore(PathBuf),
+    PrivateKey,
+    WatchOnly,
+}
+
+impl Display for AccountSource {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AccountSource::Node => f.pad("node"),
+            AccountSource::Mnemonic(index) => f.pad(&format!("hd/{}", index)),
+            AccountSource::Keystore(_) => f.pad("keystore"),
+            AccountSource::PrivateKey => f.pad("key"),
+            AccountSource::WatchOnly => f.pad("watch"),
+        }
+    }
 }
 
 impl Wallet {
-    pub fn with_mnemonic(mnemonic: Mnemonic, count: usize) -> Wallet {
-        let accounts = (0..count)
-            .map(|i| {
-                let _seed = Seed::new(&mnemonic, &format!("m/44'/60'/0'/0/{}", i));
-                unimplemented!()
+    pub fn with_mnemonic(keychain: &Keychain, count: usize) -> Result<Wallet, WalletError> {
+        let accounts = (0..count as u32)
+            .map(|i| keychain.account(i))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Wallet { accounts })
+    }
+
+    /// Discover mnemonic accounts by scanning derivation indices until `gap`
+    /// consecutive unused accounts are found. The wallet contains all accounts
+    /// up to the last used one, and always at least the first account.
+    pub fn discover<F, R>(
+        keychain: Keychain,
+        gap: usize,
+        is_used: F,
+    ) -> impl Future<Item = Wallet, Error = WalletError>
+    where
+        F: Fn(Address) -> R,
+        R: Future<Item = bool, Error = WalletError>,
+    {
+        use Either::*;
+
+        future::loop_fn(
+            (Vec::new(), 0usize),
+            move |(mut accounts, unused): (Vec<Account>, usize)| {
+                let account = match keychain.account(accounts.len() as u32) {
+                    Ok(account) => account,
+                    Err(err) => return A(future::err(err)),
+                };
+
+                B(is_used(account.public).map(move |used| {
+                    accounts.push(account);
+                    let unused = if used { 0 } else { unused + 1 };
+                    if unused < gap {
+                        return Loop::Continue((accounts, unused));
+                    }
+
+                    let last_used = accounts.len() - unused;
+                    accounts.truncate(last_used.max(1));
+                    Loop::Break(Wallet { accounts })
+                }))
+            },
+        )
+    }
+
+    /// Load accounts from Web3 Secret Storage key files. The password callback
+    /// is invoked once for every key file.
+    pub fn with_keystores<I, P, F>(paths: I, mut password: F) -> Result<Wallet, WalletError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        F: FnMut(&Path) -> Result<String, IoError>,
+    {
+        let accounts = paths
+            .into_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let key_file = KeyFile::load(path)
+                    .map_err(|err| WalletError::Keystore(path.to_owned(), err))?;
+                let password = password(path).map_err(WalletError::Prompt)?;
+                let secret = key_file
+                    .decrypt(&password)
+                    .map_err(|err| WalletError::Keystore(path.to_owned(), err))?;
+
+                Ok(Account {
+                    public: secret.public().address().into(),
+                    private: Some(secret),
+                    source: AccountSource::Keystore(path.to_owned()),
+                })
             })
-            .collect();
-        Wallet { accounts }
+            .collect::<Result<_, WalletError>>()?;
+
+        Ok(Wallet { accounts })
+    }
+
+    /// Load accounts from hex encoded private keys. Key files may contain
+    /// multiple keys, one per line, with empty lines and '#' comments ignored.
+    pub fn with_private_keys<I, F>(sources: I, mut prompt: F) -> Result<Wallet, WalletError>
+    where
+        I: IntoIterator<Item = PrivateKeySource>,
+        F: FnMut() -> Result<String, IoError>,
+    {
+        let mut accounts = Vec::new();
+        for source in sources {
+            let keys = match source {
+                PrivateKeySource::File(path) => fs::read_to_string(&path)
+                    .map_err(|err| WalletError::PrivateKeyFile(path, err))?,
+                PrivateKeySource::Env(name) => {
+                    env::var(&name).map_err(|_| WalletError::MissingEnv(name))?
+                }
+                PrivateKeySource::Prompt => prompt().map_err(WalletError::Prompt)?,
+            };
+
+            for key in keys
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|line| !line.is_empty())
+            {
+                let secret = parse_private_key(key)?;
+                accounts.push(Account {
+                    public: secret.public().address().into(),
+                    private: Some(secret),
+                    source: AccountSource::PrivateKey,
+                });
+            }
+        }
+
+        Ok(Wallet { accounts })
     }
 
     pub fn local<T>(web3: Web3<T>) -> impl Future<Item = Wallet, Error = Web3Error>
@@ -34,14 +187,354 @@ impl Wallet {
                 .iter()
                 .map(|account| Account {
                     public: *account,
-                    _private: None,
+                    private: None,
+                    source: AccountSource::Node,
                 })
                 .collect();
             Ok(Wallet { accounts })
         })
     }
 
-    pub fn accounts<'a>(&'a self) -> impl Iterator<Item = Address> + 'a {
-        self.accounts.iter().map(|account| account.public)
+    /// Creates a wallet of watch-only addresses that can be used to view
+    /// balances but not to send transactions.
+    pub fn watch_only<I>(addresses: I) -> Wallet
+    where
+        I: IntoIterator<Item = Address>,
+    {
+        let accounts = addresses
+            .into_iter()
+            .map(|address| Account {
+                public: address,
+                private: None,
+                source: AccountSource::WatchOnly,
+            })
+            .collect();
+        Wallet { accounts }
+    }
+
+    /// Merges the accounts of another wallet into this one. When an address is
+    /// present in both wallets, the account that can sign transactions wins.
+    pub fn merge(&mut self, other: Wallet) {
+        for account in other.accounts {
+            match self
+                .accounts
+                .iter_mut()
+                .find(|existing| existing.public == account.public)
+            {
+                Some(existing) => {
+                    if (existing.private.is_none() && account.private.is_some())
+                        || (existing.is_watch_only() && !account.is_watch_only())
+                    {
+                        *existing = account;
+                    }
+                }
+                None => self.accounts.push(account),
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.iter()
+    }
+
+    /// Export every account with a private key to an encrypted key file in the
+    /// specified directory, returning the paths of the created files.
+    pub fn export_keystores<P>(&self, dir: P, password: &str) -> Result<Vec<PathBuf>, WalletError>
+    where
+        P: AsRef<Path>,
+    {
+        self.accounts
+            .iter()
+            .filter_map(|account| Some((account.public, account.private.as_ref()?)))
+            .map(|(address, secret)| {
+                let path = dir.as_ref().join(format!("{:x}.json", address));
+                KeyFile::encrypt(secret, password)
+                    .and_then(|key_file| key_file.save(&path))
+                    .map_err(|err| WalletError::Keystore(path.clone(), err))?;
+
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+pub struct Keychain {
+    master: ExtendedPrivateKey,
+    template: PathTemplate,
+}
+
+impl Keychain {
+    pub fn new(
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        template: PathTemplate,
+    ) -> Result<Keychain, WalletError> {
+        let seed = Seed::new(mnemonic, passphrase);
+        let master = ExtendedPrivateKey::master(seed.as_bytes())?;
+
+        Ok(Keychain { master, template })
+    }
+
+    fn account(&self, index: u32) -> Result<Account, WalletError> {
+        let path = self.template.path(index)?;
+        let secret = self.master.derive(&path)?.secret()?;
+
+        Ok(Account {
+            public: secret.public().address().into(),
+            private: Some(secret),
+            source: AccountSource::Mnemonic(index),
+        })
+    }
+}
+
+pub enum PrivateKeySource {
+    File(PathBuf),
+    Env(String),
+    Prompt,
+}
+
+fn parse_private_key(key: &str) -> Result<SecretKey, WalletError> {
+    let key = key.trim_start_matches("0x");
+    let raw: Vec<u8> = key.from_hex().map_err(|_| WalletError::InvalidPrivateKey)?;
+    if raw.len() != 32 {
+        return Err(WalletError::InvalidPrivateKey);
+    }
+
+    SecretKey::from_raw(&raw).map_err(|_| WalletError::InvalidPrivateKey)
+}
+
+#[derive(Debug, Error)]
+pub enum WalletError {
+    #[error("failed to derive HD wallet account: {0}")]
+    Bip32(#[from] Bip32Error),
+
+    #[error("key file {0:?}: {1}")]
+    Keystore(PathBuf, #[source] KeystoreError),
+
+    #[error("failed to read private key file {0:?}: {1}")]
+    PrivateKeyFile(PathBuf, #[source] IoError),
+
+    #[error("failed to read from the terminal: {0}")]
+    Prompt(#[source] IoError),
+
+    #[error("environment variable {0} with private keys is not set")]
+    MissingEnv(String),
+
+    // NOTE: never include the key material in the error message
+    #[error("invalid hex encoded private key")]
+    InvalidPrivateKey,
+
+    #[error("failed to query account activity: {0}")]
+    Context(#[from] ContextError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const KEY_ADDRESS: &str = "2c7536e3605d9c16a7a3d7b1898e529396a65c23";
+    const ONE: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const ONE_ADDRESS: &str = "7e5f4552091a69125d5dfcb7b8c2659029395bdf";
+
+    fn address(hex: &str) -> Address {
+        hex.parse().unwrap()
+    }
+
+    fn addresses(wallet: &Wallet) -> Vec<Address> {
+        wallet.accounts().map(Account::address).collect()
+    }
+
+    fn no_prompt() -> Result<String, IoError> {
+        panic!("unexpected prompt")
+    }
+
+    #[test]
+    fn parse_private_key_with_and_without_prefix() {
+        for key in &[KEY.to_string(), format!("0x{}", KEY)] {
+            let secret = parse_private_key(key).unwrap();
+            assert_eq!(
+                Address::from(secret.public().address()),
+                address(KEY_ADDRESS)
+            );
+        }
+    }
+
+    #[test]
+    fn parse_private_key_rejects_invalid_keys() {
+        let short = &KEY[2..];
+        let long = format!("{}00", KEY);
+        let not_hex = format!("{}zz", short);
+        let zero = "00".repeat(32);
+        for key in &["", "0x", short, &long[..], &not_hex[..], &zero[..]] {
+            match parse_private_key(key) {
+                Err(WalletError::InvalidPrivateKey) => {}
+                result => panic!("unexpected result for {:?}: {:?}", key, result.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn private_keys_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("scam-ico-{:016x}.keys", rand::random::<u64>()));
+        fs::write(
+            &path,
+            format!(
+                "# deploy keys\n\n0x{}  # deployer\n   \n{}\n# {}\n",
+                KEY, ONE, KEY
+            ),
+        )
+        .unwrap();
+
+        let wallet =
+            Wallet::with_private_keys(vec![PrivateKeySource::File(path.clone())], no_prompt);
+        fs::remove_file(&path).unwrap();
+        let wallet = wallet.unwrap();
+
+        assert_eq!(
+            addresses(&wallet),
+            vec![address(KEY_ADDRESS), address(ONE_ADDRESS)]
+        );
+        assert!(wallet.accounts().all(|account| {
+            account.secret().is_some() && *account.source() == AccountSource::PrivateKey
+        }));
+    }
+
+    #[test]
+    fn private_keys_from_env_and_prompt() {
+        let name = format!("SCAM_ICO_TEST_KEYS_{:016x}", rand::random::<u64>());
+        env::set_var(&name, format!("0x{}", ONE));
+
+        let wallet = Wallet::with_private_keys(
+            vec![
+                PrivateKeySource::Env(name.clone()),
+                PrivateKeySource::Prompt,
+            ],
+            || Ok(format!("{}\n", KEY)),
+        );
+        env::remove_var(&name);
+
+        assert_eq!(
+            addresses(&wallet.unwrap()),
+            vec![address(ONE_ADDRESS), address(KEY_ADDRESS)]
+        );
+    }
+
+    #[test]
+    fn private_keys_errors() {
+        let name = format!("SCAM_ICO_TEST_KEYS_{:016x}", rand::random::<u64>());
+        match Wallet::with_private_keys(vec![PrivateKeySource::Env(name.clone())], no_prompt) {
+            Err(WalletError::MissingEnv(ref missing)) if *missing == name => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("scam-ico-{:016x}.keys", rand::random::<u64>()));
+        match Wallet::with_private_keys(vec![PrivateKeySource::File(path)], no_prompt) {
+            Err(WalletError::PrivateKeyFile(_, ref err)) if err.kind() == ErrorKind::NotFound => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+
+        // the error must not leak the (almost valid) key material
+        let key = &KEY[2..];
+        match Wallet::with_private_keys(vec![PrivateKeySource::Prompt], || Ok(key.to_string())) {
+            Err(err @ WalletError::InvalidPrivateKey) => assert!(!err.to_string().contains(key)),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+
+    fn account(byte: u8, source: AccountSource) -> Account {
+        let private = match source {
+            AccountSource::Node | AccountSource::WatchOnly => None,
+            _ => Some(parse_private_key(KEY).unwrap()),
+        };
+        Account {
+            public: Address::repeat_byte(byte),
+            private,
+            source,
+        }
+    }
+
+    fn merged(first: Vec<Account>, second: Vec<Account>) -> Vec<(Address, AccountSource)> {
+        let mut wallet = Wallet { accounts: first };
+        wallet.merge(Wallet { accounts: second });
+        wallet
+            .accounts()
+            .map(|account| (account.address(), account.source().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_prefers_signing_accounts() {
+        let node = || account(1, AccountSource::Node);
+        let watch = || account(1, AccountSource::WatchOnly);
+        let key = || account(1, AccountSource::PrivateKey);
+        let expected = vec![(Address::repeat_byte(1), AccountSource::PrivateKey)];
+
+        assert_eq!(merged(vec![node()], vec![key()]), expected);
+        assert_eq!(merged(vec![key()], vec![node()]), expected);
+        assert_eq!(merged(vec![watch()], vec![key()]), expected);
+        assert_eq!(merged(vec![key()], vec![watch()]), expected);
+    }
+
+    #[test]
+    fn merge_prefers_node_accounts_over_watch_only() {
+        let node = || account(1, AccountSource::Node);
+        let watch = || account(1, AccountSource::WatchOnly);
+        let expected = vec![(Address::repeat_byte(1), AccountSource::Node)];
+
+        assert_eq!(merged(vec![watch()], vec![node()]), expected);
+        assert_eq!(merged(vec![node()], vec![watch()]), expected);
+    }
+
+    #[test]
+    fn merge_keeps_first_of_equivalent_accounts() {
+        let keystore = AccountSource::Keystore(PathBuf::from("key.json"));
+        assert_eq!(
+            merged(
+                vec![account(1, keystore.clone())],
+                vec![account(1, AccountSource::Mnemonic(0))]
+            ),
+            vec![(Address::repeat_byte(1), keystore)]
+        );
+        assert_eq!(
+            merged(
+                vec![account(1, AccountSource::WatchOnly)],
+                vec![
+                    account(1, AccountSource::WatchOnly),
+                    account(2, AccountSource::WatchOnly),
+                    account(2, AccountSource::WatchOnly),
+                ]
+            ),
+            vec![
+                (Address::repeat_byte(1), AccountSource::WatchOnly),
+                (Address::repeat_byte(2), AccountSource::WatchOnly),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_appends_new_accounts_in_order() {
+        assert_eq!(
+            merged(
+                vec![account(2, AccountSource::Node)],
+                vec![
+                    account(3, AccountSource::Mnemonic(0)),
+                    account(2, AccountSource::Mnemonic(1)),
+                    account(1, AccountSource::WatchOnly),
+                ]
+            ),
+            vec![
+                (Address::repeat_byte(2), AccountSource::Mnemonic(1)),
+                (Address::repeat_byte(3), AccountSource::Mnemonic(0)),
+                (Address::repeat_byte(1), AccountSource::WatchOnly),
+            ]
+        );
     }
 }